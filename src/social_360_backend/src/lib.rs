@@ -3,11 +3,16 @@ use ic_cdk::api::time;
 use ic_cdk_macros::*;
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
 use ic_stable_structures::storable::Bound;
-use ic_stable_structures::{Cell, DefaultMemoryImpl, StableBTreeMap, Storable};
+use ic_stable_structures::{Cell, DefaultMemoryImpl, Log as StableLog, StableBTreeMap, Storable};
 use std::borrow::Cow;
 use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use serde_json::json;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 
@@ -20,6 +25,71 @@ const LIKES_MEMORY_ID: MemoryId = MemoryId::new(5);
 const REPOSTS_MEMORY_ID: MemoryId = MemoryId::new(6);
 const MODERATION_MEMORY_ID: MemoryId = MemoryId::new(7);
 const WARNINGS_MEMORY_ID: MemoryId = MemoryId::new(8);
+const ROLES_MEMORY_ID: MemoryId = MemoryId::new(9);
+const APPEALS_MEMORY_ID: MemoryId = MemoryId::new(10);
+const NEXT_APPEAL_ID_MEMORY_ID: MemoryId = MemoryId::new(11);
+const ENFORCEMENT_POLICY_MEMORY_ID: MemoryId = MemoryId::new(12);
+const SUSPENSIONS_MEMORY_ID: MemoryId = MemoryId::new(13);
+const WARNING_HISTORY_MEMORY_ID: MemoryId = MemoryId::new(14);
+const MODERATION_RULES_MEMORY_ID: MemoryId = MemoryId::new(15);
+const NEXT_RULE_ID_MEMORY_ID: MemoryId = MemoryId::new(16);
+const REPORT_THRESHOLDS_MEMORY_ID: MemoryId = MemoryId::new(17);
+const REPORT_SUMMARIES_MEMORY_ID: MemoryId = MemoryId::new(18);
+const AUDIT_LOG_INDEX_MEMORY_ID: MemoryId = MemoryId::new(19);
+const AUDIT_LOG_DATA_MEMORY_ID: MemoryId = MemoryId::new(20);
+const QUEUE_CLAIMS_MEMORY_ID: MemoryId = MemoryId::new(21);
+const REPORTER_STATS_MEMORY_ID: MemoryId = MemoryId::new(22);
+const RATE_LIMIT_POLICY_MEMORY_ID: MemoryId = MemoryId::new(23);
+const RATE_LIMITS_MEMORY_ID: MemoryId = MemoryId::new(24);
+const FINGERPRINTS_MEMORY_ID: MemoryId = MemoryId::new(25);
+const SPAM_POLICY_MEMORY_ID: MemoryId = MemoryId::new(26);
+const HANDLES_MEMORY_ID: MemoryId = MemoryId::new(27);
+const HANDLE_HISTORY_MEMORY_ID: MemoryId = MemoryId::new(28);
+const HANDLE_RESERVATIONS_MEMORY_ID: MemoryId = MemoryId::new(29);
+const PENDING_DELETIONS_MEMORY_ID: MemoryId = MemoryId::new(30);
+const FOLLOW_REQUESTS_MEMORY_ID: MemoryId = MemoryId::new(31);
+const NEXT_WARNING_ID_MEMORY_ID: MemoryId = MemoryId::new(32);
+
+const HOUR_NS: u64 = 3_600_000_000_000;
+const WARNING_SWEEP_INTERVAL: Duration = Duration::from_secs(15 * 60);
+const SUSPENSION_SWEEP_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const DELETION_SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const QUEUE_CLAIM_DURATION_NS: u64 = 30 * 60 * 1_000_000_000;
+const MAX_QUEUE_PAGE_SIZE: u64 = 50;
+// Reporter reputation only kicks in once enough of their flags were reviewed
+const MIN_REVIEWED_REPORTS: u32 = 5;
+const THROTTLE_ACCURACY: f32 = 0.35;
+const IGNORE_ACCURACY: f32 = 0.15;
+const THROTTLED_REPORT_INTERVAL_NS: u64 = HOUR_NS;
+const DOWNRANK_PENALTY_NS: u64 = 24 * HOUR_NS;
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+// Writes that need a registered caller; inspect_message drops them early otherwise
+const REGISTERED_WRITE_METHODS: [&str; 16] = [
+    "post_update", "repost_update", "quote_update", "like_update", "follow",
+    "flag_update", "set_update_labels", "set_content_preferences", "file_appeal",
+    "update_profile", "change_handle", "request_account_deletion", "cancel_account_deletion",
+    "set_account_privacy", "approve_follow_request", "deny_follow_request",
+];
+const MIN_HANDLE_LENGTH: usize = 3;
+const MAX_HANDLE_LENGTH: usize = 20;
+// Old handles stay with their previous owner this long after a change
+const HANDLE_RESERVATION_NS: u64 = 30 * 24 * HOUR_NS;
+const HANDLE_CHANGE_COOLDOWN_NS: u64 = 24 * HOUR_NS;
+const DELETION_GRACE_PERIOD_NS: u64 = 14 * 24 * HOUR_NS;
+const MAX_DISPLAY_NAME_LENGTH: usize = 50;
+const MAX_BIO_LENGTH: usize = 160;
+const MAX_LOCATION_LENGTH: usize = 30;
+const MAX_URL_LENGTH: usize = 200;
+// Bumped whenever a field in the export documents changes meaning or is removed
+const EXPORT_FORMAT_VERSION: u32 = 1;
+// Leaves headroom under the 2MiB reply limit for the candid envelope
+const MAX_EXPORT_CHUNK_BYTES: usize = 1_000_000;
+const RESERVED_HANDLES: [&str; 24] = [
+    "admin", "administrator", "moderator", "mod", "support", "help", "system", "root",
+    "owner", "staff", "official", "security", "api", "null", "undefined", "anonymous",
+    "everyone", "settings", "explore", "home", "login", "logout", "signup", "register",
+];
 
 thread_local! {
     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
@@ -77,6 +147,142 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(WARNINGS_MEMORY_ID)),
         )
     );
+
+    static ROLES: RefCell<StableBTreeMap<Principal, Role, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(ROLES_MEMORY_ID)),
+        )
+    );
+
+    static APPEALS: RefCell<StableBTreeMap<u64, Appeal, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(APPEALS_MEMORY_ID)),
+        )
+    );
+
+    static NEXT_APPEAL_ID: RefCell<Cell<u64, Memory>> = RefCell::new(
+        Cell::init(MEMORY_MANAGER.with(|m| m.borrow().get(NEXT_APPEAL_ID_MEMORY_ID)), 0)
+            .expect("Cannot initialize next appeal ID")
+    );
+
+    static NEXT_WARNING_ID: RefCell<Cell<u64, Memory>> = RefCell::new(
+        Cell::init(MEMORY_MANAGER.with(|m| m.borrow().get(NEXT_WARNING_ID_MEMORY_ID)), 0)
+            .expect("Cannot initialize next warning ID")
+    );
+
+    static ENFORCEMENT_POLICY: RefCell<Cell<EnforcementPolicy, Memory>> = RefCell::new(
+        Cell::init(MEMORY_MANAGER.with(|m| m.borrow().get(ENFORCEMENT_POLICY_MEMORY_ID)), EnforcementPolicy::default())
+            .expect("Cannot initialize enforcement policy")
+    );
+
+    static SUSPENSIONS: RefCell<StableBTreeMap<Principal, StableVec<SuspensionRecord>, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SUSPENSIONS_MEMORY_ID)),
+        )
+    );
+
+    static WARNING_HISTORY: RefCell<StableBTreeMap<Principal, StableVec<HistoricalWarning>, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(WARNING_HISTORY_MEMORY_ID)),
+        )
+    );
+
+    static MODERATION_RULES: RefCell<StableBTreeMap<u64, ModerationRule, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MODERATION_RULES_MEMORY_ID)),
+        )
+    );
+
+    static NEXT_RULE_ID: RefCell<Cell<u64, Memory>> = RefCell::new(
+        Cell::init(MEMORY_MANAGER.with(|m| m.borrow().get(NEXT_RULE_ID_MEMORY_ID)), 0)
+            .expect("Cannot initialize next rule ID")
+    );
+
+    static REPORT_THRESHOLDS: RefCell<Cell<ReportThresholds, Memory>> = RefCell::new(
+        Cell::init(MEMORY_MANAGER.with(|m| m.borrow().get(REPORT_THRESHOLDS_MEMORY_ID)), ReportThresholds::default())
+            .expect("Cannot initialize report thresholds")
+    );
+
+    static REPORT_SUMMARIES: RefCell<StableBTreeMap<u64, ReportSummary, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(REPORT_SUMMARIES_MEMORY_ID)),
+        )
+    );
+
+    static AUDIT_LOG: RefCell<StableLog<AuditEntry, Memory, Memory>> = RefCell::new(
+        StableLog::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(AUDIT_LOG_INDEX_MEMORY_ID)),
+            MEMORY_MANAGER.with(|m| m.borrow().get(AUDIT_LOG_DATA_MEMORY_ID)),
+        ).expect("Cannot initialize audit log")
+    );
+
+    static QUEUE_CLAIMS: RefCell<StableBTreeMap<u64, QueueClaim, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(QUEUE_CLAIMS_MEMORY_ID)),
+        )
+    );
+
+    static REPORTER_STATS: RefCell<StableBTreeMap<Principal, ReporterStats, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(REPORTER_STATS_MEMORY_ID)),
+        )
+    );
+
+    static RATE_LIMIT_POLICY: RefCell<Cell<RateLimitPolicy, Memory>> = RefCell::new(
+        Cell::init(MEMORY_MANAGER.with(|m| m.borrow().get(RATE_LIMIT_POLICY_MEMORY_ID)), RateLimitPolicy::default())
+            .expect("Cannot initialize rate limit policy")
+    );
+
+    static RATE_LIMITS: RefCell<StableBTreeMap<Principal, RateLimitState, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(RATE_LIMITS_MEMORY_ID)),
+        )
+    );
+
+    // Fingerprints of updates posted within the spam policy's window, keyed by update ID
+    static FINGERPRINTS: RefCell<StableBTreeMap<u64, ContentFingerprint, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(FINGERPRINTS_MEMORY_ID)),
+        )
+    );
+
+    // Lowercased handle to owner, so handles are unique regardless of case
+    static HANDLES: RefCell<StableBTreeMap<String, Principal, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(HANDLES_MEMORY_ID)),
+        )
+    );
+
+    static HANDLE_HISTORY: RefCell<StableBTreeMap<Principal, StableVec<HandleChange>, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(HANDLE_HISTORY_MEMORY_ID)),
+        )
+    );
+
+    // Lowercased handles given up recently, held for their previous owner
+    static HANDLE_RESERVATIONS: RefCell<StableBTreeMap<String, HandleReservation, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(HANDLE_RESERVATIONS_MEMORY_ID)),
+        )
+    );
+
+    static PENDING_DELETIONS: RefCell<StableBTreeMap<Principal, DeletionRequest, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(PENDING_DELETIONS_MEMORY_ID)),
+        )
+    );
+
+    // Pending requests to follow a private account, keyed by the account
+    static FOLLOW_REQUESTS: RefCell<StableBTreeMap<Principal, StableVec<FollowRequest>, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(FOLLOW_REQUESTS_MEMORY_ID)),
+        )
+    );
+
+    static SPAM_POLICY: RefCell<Cell<SpamPolicy, Memory>> = RefCell::new(
+        Cell::init(MEMORY_MANAGER.with(|m| m.borrow().get(SPAM_POLICY_MEMORY_ID)), SpamPolicy::default())
+            .expect("Cannot initialize spam policy")
+    );
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone)]
@@ -86,10 +292,136 @@ struct User {
     warning_count: u32,
     is_suspended: bool,
     suspension_until: Option<u64>,
+    content_preferences: Option<Vec<(ContentLabel, LabelPreference)>>, // Unset labels use the defaults
+    joined_at: Option<u64>, // Backfilled on upgrade for accounts registered before it was recorded
+    display_name: Option<String>,
+    bio: Option<String>,
+    location: Option<String>,
+    website: Option<String>,
+    avatar_url: Option<String>,
+    banner_url: Option<String>,
+    is_private: Option<bool>, // Only approved followers see a private account's updates
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+struct FollowRequest {
+    requester: Principal,
+    requested_at: u64,
+}
+
+impl Storable for FollowRequest {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(self).unwrap().into() }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self { candid::decode_one(bytes.as_ref()).unwrap() }
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+// What `follow` did: private accounts have to approve new followers first
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+enum FollowState {
+    Following,
+    Requested,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+struct HandleChange {
+    old_handle: String,
+    new_handle: String,
+    changed_at: u64,
+}
+
+impl Storable for HandleChange {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(self).unwrap().into() }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self { candid::decode_one(bytes.as_ref()).unwrap() }
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+struct HandleReservation {
+    owner: Principal,
+    expires_at: u64,
+}
+
+impl Storable for HandleReservation {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(self).unwrap().into() }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self { candid::decode_one(bytes.as_ref()).unwrap() }
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+struct DeletionRequest {
+    requested_at: u64,
+    purge_at: u64,
+}
+
+impl Storable for DeletionRequest {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(self).unwrap().into() }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self { candid::decode_one(bytes.as_ref()).unwrap() }
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+// Replaces every profile field; empty or missing values clear the field
+#[derive(CandidType, Deserialize, Clone)]
+struct ProfileInput {
+    display_name: Option<String>,
+    bio: Option<String>,
+    location: Option<String>,
+    website: Option<String>,
+    avatar_url: Option<String>,
+    banner_url: Option<String>,
+}
+
+// Sections are exported in declaration order
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+enum ExportSection {
+    Profile,
+    Updates,
+    Likes,
+    Following,
+    Followers,
+    Warnings,
+    Reports,
+}
+
+impl ExportSection {
+    fn next(&self) -> Option<ExportSection> {
+        match self {
+            ExportSection::Profile => Some(ExportSection::Updates),
+            ExportSection::Updates => Some(ExportSection::Likes),
+            ExportSection::Likes => Some(ExportSection::Following),
+            ExportSection::Following => Some(ExportSection::Followers),
+            ExportSection::Followers => Some(ExportSection::Warnings),
+            ExportSection::Warnings => Some(ExportSection::Reports),
+            ExportSection::Reports => None,
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+struct ExportCursor {
+    section: ExportSection,
+    offset: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+struct ExportChunk {
+    format_version: u32,
+    section: ExportSection,
+    data: String, // JSON document, see "Exporting your data" in the README
+    next_cursor: Option<ExportCursor>,
+}
+
+// JSON envelope around each chunk's items
+#[derive(Serialize)]
+struct ExportDocument<'a> {
+    format_version: u32,
+    section: ExportSection,
+    offset: u64,
+    total: u64,
+    items: &'a [serde_json::Value],
 }
 
 impl Storable for User {
-    fn to_bytes(&self) -> Cow<[u8]> { candid::encode_one(self).unwrap().into() }
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(self).unwrap().into() }
     fn from_bytes(bytes: Cow<[u8]>) -> Self { candid::decode_one(bytes.as_ref()).unwrap() }
     const BOUND: Bound = Bound::Unbounded;
 }
@@ -108,14 +440,227 @@ struct Update {
     is_moderated: bool,
     moderation_reason: Option<String>,
     is_hidden: bool,
+    moderation_rule_id: Option<u64>, // Rule that auto-moderated the update
+    content_action: Option<ContentAction>,
+    author_labels: Option<Vec<ContentLabel>>,
+    moderator_labels: Option<Vec<ContentLabel>>, // Authors can't remove these
+    deleted_at: Option<u64>, // Tombstone kept for moderation after the author deleted their account
 }
 
 impl Storable for Update {
-    fn to_bytes(&self) -> Cow<[u8]> { candid::encode_one(self).unwrap().into() }
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(self).unwrap().into() }
     fn from_bytes(bytes: Cow<[u8]>) -> Self { candid::decode_one(bytes.as_ref()).unwrap() }
     const BOUND: Bound = Bound::Unbounded;
 }
 
+impl Update {
+    // quote_update prefixes the quoting text; quote_content can't be relied on
+    // since it is cleared while the quoted original is moderated
+    fn is_quote(&self) -> bool {
+        self.original_post_id.is_some() && self.content.starts_with("Quote: ")
+    }
+
+    fn labels(&self) -> Vec<ContentLabel> {
+        let mut labels: Vec<ContentLabel> = self.author_labels.iter().chain(self.moderator_labels.iter())
+            .flatten()
+            .copied()
+            .collect();
+        labels.sort();
+        labels.dedup();
+        labels
+    }
+
+    // Updates hidden before the action ladder existed only have is_hidden set
+    fn effective_action(&self) -> Option<ContentAction> {
+        self.content_action.or(if self.is_hidden { Some(ContentAction::Hide) } else { None })
+    }
+
+    fn visible_to(&self, viewer: Principal) -> bool {
+        if self.deleted_at.is_some() {
+            return false;
+        }
+        if viewer != self.author && is_private_account(self.author) && !is_following(viewer, self.author) {
+            return false;
+        }
+        match self.effective_action() {
+            Some(ContentAction::Hide) | Some(ContentAction::Remove) => false,
+            Some(ContentAction::FollowersOnly) => viewer == self.author || is_following(viewer, self.author),
+            _ => true,
+        }
+    }
+
+    fn is_discoverable(&self) -> bool {
+        self.effective_action().is_none_or(|action| action < ContentAction::ExcludeFromDiscovery)
+    }
+
+    // Downranked updates sort as if they were posted a day earlier
+    fn ranking_timestamp(&self) -> u64 {
+        match self.effective_action() {
+            Some(action) if action >= ContentAction::Downrank => self.timestamp.saturating_sub(DOWNRANK_PENALTY_NS),
+            _ => self.timestamp,
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum ContentLabel {
+    Nsfw,
+    Spoiler,
+    Graphic,
+    Sensitive,
+}
+
+impl ContentLabel {
+    const ALL: [ContentLabel; 4] = [ContentLabel::Nsfw, ContentLabel::Spoiler, ContentLabel::Graphic, ContentLabel::Sensitive];
+
+    fn default_preference(self) -> LabelPreference {
+        match self {
+            ContentLabel::Sensitive => LabelPreference::Show,
+            _ => LabelPreference::Blur,
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum LabelPreference {
+    Show,
+    Blur,
+    Hide,
+}
+
+// An update as served to a particular viewer
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+struct FeedItem {
+    update: Update,
+    blurred_labels: Vec<ContentLabel>, // Labels the viewer wants behind a click-through
+    notice: Option<String>,            // Why others can't see what this viewer sees
+    interstitial: Option<String>,      // Moderator label to show before the content
+    original: Option<Update>,          // Quoted or reposted update, if the viewer may see it
+}
+
+enum Visibility {
+    Visible,
+    WithNotice(String),
+    Hidden,
+}
+
+// Every read endpoint decides what to return through the caller's Viewer
+struct Viewer {
+    principal: Principal,
+    is_moderator: bool,
+    preferences: Vec<(ContentLabel, LabelPreference)>,
+}
+
+impl Viewer {
+    fn current() -> Self {
+        let principal = ic_cdk::caller();
+        Viewer {
+            principal,
+            is_moderator: role_of(&principal).rank() >= Role::Moderator.rank(),
+            preferences: viewer_preferences(principal),
+        }
+    }
+
+    // Authors keep seeing their own restricted updates and moderators see
+    // everything, both with a notice saying why others can't
+    fn visibility(&self, update: &Update) -> Visibility {
+        if update.visible_to(self.principal) {
+            return Visibility::Visible;
+        }
+        // Below FollowersOnly nothing but the author's privacy hides an update
+        let reason = match update.deleted_at {
+            Some(_) => "Retained after the author deleted their account".to_string(),
+            None if update.effective_action().is_none_or(|action| action < ContentAction::FollowersOnly) => {
+                "Private account, only approved followers can see it".to_string()
+            }
+            None => update.moderation_reason.clone().unwrap_or_else(|| "Restricted by moderators".to_string()),
+        };
+        if update.author == self.principal {
+            Visibility::WithNotice(format!("Only you can see this update: {}", reason))
+        } else if self.is_moderator {
+            Visibility::WithNotice(format!("Restricted from other users: {}", reason))
+        } else {
+            Visibility::Hidden
+        }
+    }
+
+    fn can_see(&self, update: &Update) -> bool {
+        !matches!(self.visibility(update), Visibility::Hidden)
+    }
+
+    fn view(&self, mut update: Update) -> Option<FeedItem> {
+        let notice = match self.visibility(&update) {
+            Visibility::Hidden => return None,
+            Visibility::Visible => None,
+            Visibility::WithNotice(notice) => Some(notice),
+        };
+        let shared = update.original_post_id.and_then(|id| UPDATES.with(|updates| updates.borrow().get(&id)));
+        // Shared copies pick up labels added to the original after they were made
+        let mut labels = update.labels();
+        if let Some(shared) = &shared {
+            labels.extend(shared.labels());
+            labels.sort();
+            labels.dedup();
+        }
+        // Label preferences don't apply to the viewer's own updates
+        if update.author != self.principal
+            && labels.iter().any(|label| preference_of(*label, &self.preferences) == LabelPreference::Hide)
+        {
+            return None;
+        }
+        
+        let original = shared.filter(|original| self.can_see(original));
+        if update.original_post_id.is_some() && original.is_none() {
+            if update.is_quote() {
+                update.quote_content = None;
+            } else if update.author != self.principal && !self.is_moderator {
+                // A repost is nothing but a copy of the original
+                return None;
+            }
+        }
+        
+        let blurred_labels = labels.into_iter()
+            .filter(|label| preference_of(*label, &self.preferences) == LabelPreference::Blur)
+            .collect();
+        // Every rung of the ladder still shows the Label interstitial
+        let interstitial = update.effective_action().map(|_| {
+            update.moderation_reason.clone().unwrap_or_else(|| "Labeled by moderators".to_string())
+        });
+        Some(FeedItem { update, blurred_labels, notice, interstitial, original })
+    }
+
+    fn can_see_id(&self, update_id: u64) -> bool {
+        UPDATES.with(|updates| updates.borrow().get(&update_id)).is_some_and(|update| self.can_see(&update))
+    }
+}
+
+// Each rung carries the restrictions of the rungs below it
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum ContentAction {
+    Label,                // Shown behind an interstitial
+    ExcludeFromDiscovery, // Left out of search
+    Downrank,             // Sorted below fresher updates in timelines
+    FollowersOnly,        // Only the author's followers can see it
+    Hide,
+    Remove,
+}
+
+impl ContentAction {
+    fn for_severity(severity: &ModerationSeverity) -> Self {
+        match severity {
+            ModerationSeverity::Low => ContentAction::Label,
+            ModerationSeverity::Medium => ContentAction::Downrank,
+            ModerationSeverity::High => ContentAction::Hide,
+            ModerationSeverity::Critical => ContentAction::Remove,
+        }
+    }
+
+    // Reposts and quotes would carry the content past these restrictions
+    fn restricts_sharing(self) -> bool {
+        self >= ContentAction::FollowersOnly
+    }
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone)]
 struct ModerationFlag {
     update_id: u64,
@@ -123,15 +668,129 @@ struct ModerationFlag {
     reason: String,
     severity: ModerationSeverity,
     timestamp: u64,
-    is_resolved: bool,
+    resolution: Option<FlagResolution>,
+}
+
+// Flags stored before resolutions existed only carry `is_resolved`
+#[derive(CandidType, Deserialize)]
+struct StoredModerationFlag {
+    update_id: u64,
+    flagged_by: Principal,
+    reason: String,
+    severity: ModerationSeverity,
+    timestamp: u64,
+    resolution: Option<FlagResolution>,
+    is_resolved: Option<bool>,
 }
 
 impl Storable for ModerationFlag {
-    fn to_bytes(&self) -> Cow<[u8]> { candid::encode_one(self).unwrap().into() }
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(self).unwrap().into() }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let stored: StoredModerationFlag = candid::decode_one(bytes.as_ref()).unwrap();
+        let resolution = stored.resolution.or_else(|| {
+            stored.is_resolved.filter(|resolved| *resolved).map(|_| FlagResolution {
+                outcome: FlagOutcome::Dismissed,
+                moderator: Principal::anonymous(),
+                note: Some("Resolved before outcomes were recorded".to_string()),
+                resolved_at: stored.timestamp,
+            })
+        });
+        ModerationFlag {
+            update_id: stored.update_id,
+            flagged_by: stored.flagged_by,
+            reason: stored.reason,
+            severity: stored.severity,
+            timestamp: stored.timestamp,
+            resolution,
+        }
+    }
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl ModerationFlag {
+    // Escalated flags stay in the queue until an admin settles them
+    fn is_open(&self) -> bool {
+        self.resolution.as_ref().is_none_or(|r| r.outcome == FlagOutcome::Escalated)
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+enum FlagOutcome {
+    Dismissed,      // Report was invalid; the author's warning is retracted
+    ContentRemoved, // Update is taken down; the warning stands
+    AuthorWarned,   // Update stays up; the warning stands
+    Escalated,      // Handed to an admin for a final decision
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+struct FlagResolution {
+    outcome: FlagOutcome,
+    moderator: Principal,
+    note: Option<String>,
+    resolved_at: u64,
+}
+
+// Track record of a reporter's reviewed flags
+#[derive(CandidType, Serialize, Deserialize, Clone, Default)]
+struct ReporterStats {
+    confirmed: u32,
+    dismissed: u32,
+    last_report_at: Option<u64>,
+}
+
+impl Storable for ReporterStats {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(self).unwrap().into() }
     fn from_bytes(bytes: Cow<[u8]>) -> Self { candid::decode_one(bytes.as_ref()).unwrap() }
     const BOUND: Bound = Bound::Unbounded;
 }
 
+impl ReporterStats {
+    // Laplace-smoothed, so a reporter with no history starts at 0.5
+    fn accuracy(&self) -> f32 {
+        (self.confirmed as f32 + 1.0) / ((self.confirmed + self.dismissed) as f32 + 2.0)
+    }
+
+    fn is_established(&self) -> bool {
+        self.confirmed + self.dismissed >= MIN_REVIEWED_REPORTS
+    }
+
+    fn is_throttled(&self) -> bool {
+        self.is_established() && self.accuracy() < THROTTLE_ACCURACY
+    }
+
+    fn is_ignored(&self) -> bool {
+        self.is_established() && self.accuracy() < IGNORE_ACCURACY
+    }
+
+    // An unproven reporter counts at the severity's face value
+    fn report_weight(&self, severity: &ModerationSeverity) -> f32 {
+        if self.is_ignored() {
+            return 0.0;
+        }
+        severity.report_weight() * self.accuracy() * 2.0
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+struct ReporterReputation {
+    stats: ReporterStats,
+    accuracy: f32,
+    throttled: bool,
+    ignored: bool,
+}
+
+// What a reporter gets to see about their own report
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+struct ReportStatus {
+    flag_id: u64,
+    update_id: u64,
+    reason: String,
+    severity: ModerationSeverity,
+    filed_at: u64,
+    outcome: Option<FlagOutcome>,
+    resolved_at: Option<u64>,
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone)]
 struct Warning {
     id: u64,
@@ -139,15 +798,45 @@ struct Warning {
     severity: ModerationSeverity,
     timestamp: u64,
     expires_at: Option<u64>,
+    update_id: Option<u64>, // Update that caused the warning, if any
+    flag_id: Option<u64>, // Flag whose resolution issued the warning, if any
 }
 
 impl Storable for Warning {
-    fn to_bytes(&self) -> Cow<[u8]> { candid::encode_one(self).unwrap().into() }
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(self).unwrap().into() }
     fn from_bytes(bytes: Cow<[u8]>) -> Self { candid::decode_one(bytes.as_ref()).unwrap() }
     const BOUND: Bound = Bound::Unbounded;
 }
 
-#[derive(CandidType, Serialize, Deserialize, Clone, PartialEq)]
+impl Warning {
+    fn is_active(&self, now: u64) -> bool {
+        self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum WarningOutcome {
+    Expired,
+    Retracted,
+    AccountDeleted,
+}
+
+// A warning that no longer counts towards warning_count
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+struct HistoricalWarning {
+    warning: Warning,
+    outcome: WarningOutcome,
+    archived_at: u64,
+}
+
+impl Storable for HistoricalWarning {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(self).unwrap().into() }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self { candid::decode_one(bytes.as_ref()).unwrap() }
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+// Variants are ordered from least to most severe
+#[derive(CandidType, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 enum ModerationSeverity {
     Low,
     Medium,
@@ -155,16 +844,480 @@ enum ModerationSeverity {
     Critical,
 }
 
-#[derive(CandidType, Serialize, Clone)]
-struct StableVec<T: CandidType + Serialize + DeserializeOwned + Clone + Storable>(Vec<T>);
+impl ModerationSeverity {
+    // Contribution of a single report to an update's report score
+    fn report_weight(&self) -> f32 {
+        match self {
+            ModerationSeverity::Low => 1.0,
+            ModerationSeverity::Medium => 2.0,
+            ModerationSeverity::High => 3.0,
+            ModerationSeverity::Critical => 5.0,
+        }
+    }
+}
 
-impl<T: CandidType + Serialize + DeserializeOwned + Clone + Storable> Storable for StableVec<T> {
-    fn to_bytes(&self) -> Cow<[u8]> { candid::encode_one(&self.0).unwrap().into() }
-    fn from_bytes(bytes: Cow<[u8]>) -> Self { Self(candid::decode_one(bytes.as_ref()).unwrap()) }
-    const BOUND: Bound = Bound::Unbounded;
+// An update is hidden pending review once both thresholds are reached
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+struct ReportThresholds {
+    min_reporters: u32,
+    min_score: f32,
 }
 
-#[derive(CandidType, Serialize, Deserialize, Clone)]
+impl Default for ReportThresholds {
+    fn default() -> Self {
+        ReportThresholds { min_reporters: 3, min_score: 6.0 }
+    }
+}
+
+impl Storable for ReportThresholds {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(self).unwrap().into() }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self { candid::decode_one(bytes.as_ref()).unwrap() }
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+struct ReportEntry {
+    reporter: Principal,
+    flag_id: u64,
+    weight: f32,
+}
+
+// Open reports against an update, at most one per reporter
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+struct ReportSummary {
+    update_id: u64,
+    reports: Vec<ReportEntry>,
+    score: f32,
+    auto_hidden: bool,
+    auto_hide_reason: Option<String>,
+    // Moderation in place before the auto-hide, restored if every report is dismissed
+    prior_action: Option<ContentAction>,
+    prior_reason: Option<String>,
+}
+
+impl Storable for ReportSummary {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(self).unwrap().into() }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self { candid::decode_one(bytes.as_ref()).unwrap() }
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+enum MatchMode {
+    ExactWord,
+    Substring,
+    Regex,
+}
+
+// Variants are ordered from least to most restrictive
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum RuleAction {
+    Flag,
+    Hide,
+    Reject,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+struct ModerationRuleInput {
+    pattern: String,
+    match_mode: MatchMode,
+    severity: ModerationSeverity,
+    action: RuleAction,
+    enabled: bool,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+struct ModerationRule {
+    id: u64,
+    pattern: String,
+    match_mode: MatchMode,
+    severity: ModerationSeverity,
+    action: RuleAction,
+    enabled: bool,
+    updated_by: Principal,
+    updated_at: u64,
+}
+
+impl Storable for ModerationRule {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(self).unwrap().into() }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self { candid::decode_one(bytes.as_ref()).unwrap() }
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+struct ModerationVerdict {
+    action: RuleAction,
+    reason: String,
+    severity: ModerationSeverity,
+    rule_id: Option<u64>, // None for the built-in caps and repetition checks
+}
+
+// Suspend for `suspension_hours` once `strike_count` warnings of at least
+// `min_severity` were received within the last `window_hours`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+struct EnforcementRule {
+    min_severity: ModerationSeverity,
+    strike_count: u32,
+    window_hours: u64,
+    suspension_hours: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+struct EnforcementPolicy {
+    rules: Vec<EnforcementRule>,
+}
+
+impl Default for EnforcementPolicy {
+    fn default() -> Self {
+        EnforcementPolicy {
+            rules: vec![
+                EnforcementRule { min_severity: ModerationSeverity::Medium, strike_count: 3, window_hours: 720, suspension_hours: 24 },
+                EnforcementRule { min_severity: ModerationSeverity::High, strike_count: 2, window_hours: 720, suspension_hours: 72 },
+                EnforcementRule { min_severity: ModerationSeverity::Critical, strike_count: 1, window_hours: 720, suspension_hours: 168 },
+            ],
+        }
+    }
+}
+
+impl Storable for EnforcementPolicy {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(self).unwrap().into() }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self { candid::decode_one(bytes.as_ref()).unwrap() }
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+enum WriteAction {
+    PostUpdate,
+    RepostUpdate,
+    QuoteUpdate,
+    LikeUpdate,
+    Follow,
+    FlagUpdate,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+struct RateLimitRule {
+    action: WriteAction,
+    max_requests: u32,
+    restricted_max_requests: u32, // For new or warned accounts
+    window_secs: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+struct RateLimitPolicy {
+    rules: Vec<RateLimitRule>,
+    new_account_hours: u64,
+}
+
+impl Default for RateLimitPolicy {
+    fn default() -> Self {
+        let rule = |action, max_requests, restricted_max_requests, window_secs| RateLimitRule {
+            action, max_requests, restricted_max_requests, window_secs,
+        };
+        RateLimitPolicy {
+            rules: vec![
+                rule(WriteAction::PostUpdate, 10, 3, 60),
+                rule(WriteAction::RepostUpdate, 20, 5, 60),
+                rule(WriteAction::QuoteUpdate, 10, 3, 60),
+                rule(WriteAction::LikeUpdate, 60, 20, 60),
+                rule(WriteAction::Follow, 30, 10, 60),
+                rule(WriteAction::FlagUpdate, 20, 5, 3600),
+            ],
+            new_account_hours: 24,
+        }
+    }
+}
+
+impl Storable for RateLimitPolicy {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(self).unwrap().into() }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self { candid::decode_one(bytes.as_ref()).unwrap() }
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+// Timestamps of a principal's calls still inside each action's window
+#[derive(CandidType, Serialize, Deserialize, Clone, Default)]
+struct RateLimitState {
+    recent: Vec<(WriteAction, Vec<u64>)>,
+}
+
+impl Storable for RateLimitState {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(self).unwrap().into() }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self { candid::decode_one(bytes.as_ref()).unwrap() }
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl RateLimitState {
+    // Records a call unless `limit` calls already fall inside the sliding
+    // window, in which case it returns the nanoseconds until one is allowed
+    fn admit(&mut self, action: WriteAction, limit: usize, window: u64, now: u64) -> Result<(), u64> {
+        let index = match self.recent.iter().position(|(a, _)| *a == action) {
+            Some(index) => index,
+            None => {
+                self.recent.push((action, vec![]));
+                self.recent.len() - 1
+            }
+        };
+        let calls = &mut self.recent[index].1;
+        calls.retain(|&at| now < at.saturating_add(window));
+        if calls.len() >= limit {
+            // Wait until enough of the oldest calls have left the window
+            return Err(calls[calls.len() - limit].saturating_add(window) - now);
+        }
+        calls.push(now);
+        Ok(())
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+struct ContentFingerprint {
+    author: Principal,
+    exact_hash: u64, // FNV-1a of the normalized text
+    simhash: u64,
+    token_count: u32,
+    timestamp: u64,
+}
+
+impl Storable for ContentFingerprint {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(self).unwrap().into() }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self { candid::decode_one(bytes.as_ref()).unwrap() }
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+struct SpamPolicy {
+    window_hours: u64,
+    max_self_duplicates: u32,     // Further copies of the caller's own updates are rejected
+    cross_account_threshold: u32, // Distinct other authors before an update is flagged
+    max_hamming_distance: u32,    // SimHash bits two near-duplicates may differ in
+    min_tokens: u32,              // Shorter texts only match exactly, and never across accounts
+}
+
+impl Default for SpamPolicy {
+    fn default() -> Self {
+        SpamPolicy { window_hours: 24, max_self_duplicates: 2, cross_account_threshold: 5, max_hamming_distance: 10, min_tokens: 5 }
+    }
+}
+
+impl Storable for SpamPolicy {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(self).unwrap().into() }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self { candid::decode_one(bytes.as_ref()).unwrap() }
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+enum SuspensionSource {
+    Moderator(Principal),
+    Automatic,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+struct SuspensionRecord {
+    suspended_at: u64,
+    until: u64,
+    reason: String,
+    source: SuspensionSource,
+}
+
+impl Storable for SuspensionRecord {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(self).unwrap().into() }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self { candid::decode_one(bytes.as_ref()).unwrap() }
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, PartialEq)]
+enum AppealTarget {
+    Update(u64),
+    Warning(u64),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+enum AppealStatus {
+    Pending,
+    Upheld,
+    Overturned,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum AppealDecision {
+    Upheld,
+    Overturned,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+struct Appeal {
+    id: u64,
+    appellant: Principal,
+    target: AppealTarget,
+    statement: String,
+    status: AppealStatus,
+    filed_at: u64,
+    reviewed_by: Option<Principal>,
+    reviewed_at: Option<u64>,
+    review_note: Option<String>,
+}
+
+impl Storable for Appeal {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(self).unwrap().into() }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self { candid::decode_one(bytes.as_ref()).unwrap() }
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+// A moderator's lock on every open flag for one update
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+struct QueueClaim {
+    moderator: Principal,
+    assigned_by: Option<Principal>, // Set when an admin assigned the item
+    claimed_at: u64,
+    expires_at: u64,
+}
+
+impl Storable for QueueClaim {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(self).unwrap().into() }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self { candid::decode_one(bytes.as_ref()).unwrap() }
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+// Everything a moderator needs to decide on one flagged update
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+struct QueueItem {
+    update_id: u64,
+    update: Option<Update>,
+    author: Option<User>,
+    flags: Vec<(u64, ModerationFlag)>,
+    max_severity: ModerationSeverity,
+    oldest_flag_at: u64,
+    report_score: f32,
+    claim: Option<QueueClaim>,
+    author_warnings: Vec<Warning>,
+    author_warning_history: Vec<HistoricalWarning>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+struct QueuePage {
+    items: Vec<QueueItem>,
+    total: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+enum AuditAction {
+    ModerateUpdate,
+    UnmoderateUpdate,
+    AutoHideUpdate,
+    ResolveFlag,
+    SuspendUser,
+    UnsuspendUser,
+    VerifyUser,
+    GrantRole,
+    RevokeRole,
+    TransferOwnership,
+    DecideAppeal,
+    AssignQueueItem,
+    LabelUpdate,
+    CreateRule,
+    UpdateRule,
+    DeleteRule,
+    UpdateSettings,
+    DeleteAccount,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, PartialEq)]
+enum AuditTarget {
+    Update(u64),
+    User(Principal),
+    Flag(u64),
+    Appeal(u64),
+    Rule(u64),
+    Setting(String),
+}
+
+// Automatic actions are recorded with the canister itself as the actor
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+struct AuditEntry {
+    id: u64,
+    actor: Principal,
+    action: AuditAction,
+    target: AuditTarget,
+    reason: Option<String>,
+    severity: Option<ModerationSeverity>,
+    timestamp: u64,
+    before: Option<String>,
+    after: Option<String>,
+}
+
+impl Storable for AuditEntry {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(self).unwrap().into() }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self { candid::decode_one(bytes.as_ref()).unwrap() }
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+// All filters are optional; pages run from newest to oldest
+#[derive(CandidType, Deserialize, Clone)]
+struct AuditQuery {
+    actor: Option<Principal>,
+    action: Option<AuditAction>,
+    target: Option<AuditTarget>,
+    since: Option<u64>,
+    until: Option<u64>,
+    before_id: Option<u64>, // Cursor from a previous page
+    limit: Option<u32>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+struct AuditPage {
+    entries: Vec<AuditEntry>,
+    next_cursor: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+enum Error {
+    NotFound,
+    NotAuthorized,
+    AlreadyExists,
+    InvalidArgument(String),
+    Suspended { until: Option<u64> },
+    ContentRejected { reason: String, rule_id: Option<u64> },
+    UpdateUnavailable,
+    RateLimited { retry_after_secs: u64 },
+    NotRegistered,
+    Anonymous,
+    HandleTaken,
+}
+
+// Principals without an entry in ROLES are regular users
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+enum Role {
+    Owner,
+    Admin,
+    Moderator,
+    User,
+}
+
+impl Role {
+    fn rank(&self) -> u8 {
+        match self {
+            Role::User => 0,
+            Role::Moderator => 1,
+            Role::Admin => 2,
+            Role::Owner => 3,
+        }
+    }
+}
+
+impl Storable for Role {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(self).unwrap().into() }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self { candid::decode_one(bytes.as_ref()).unwrap() }
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, Clone)]
+struct StableVec<T: CandidType + Serialize + DeserializeOwned + Clone + Storable>(Vec<T>);
+
+impl<T: CandidType + Serialize + DeserializeOwned + Clone + Storable> Storable for StableVec<T> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { candid::encode_one(&self.0).unwrap().into() }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self { Self(candid::decode_one(bytes.as_ref()).unwrap()) }
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
 struct AIInsights {
     content_score: u8, // 0-100
     engagement_prediction: EngagementPrediction,
@@ -198,6 +1351,80 @@ enum ContentCategory {
     Other,
 }
 
+// Appends to the audit log; entries can never be edited or removed
+fn record_audit(
+    actor: Principal,
+    action: AuditAction,
+    target: AuditTarget,
+    reason: Option<String>,
+    severity: Option<ModerationSeverity>,
+    before: Option<String>,
+    after: Option<String>,
+) {
+    AUDIT_LOG.with(|log| {
+        let log = log.borrow();
+        let entry = AuditEntry { id: log.len(), actor, action, target, reason, severity, timestamp: time(), before, after };
+        log.append(&entry).expect("Cannot append to audit log");
+    });
+}
+
+// State snapshots for the audit log's before/after fields
+fn update_snapshot(update_id: u64) -> Option<String> {
+    UPDATES.with(|updates| updates.borrow().get(&update_id)).map(|update| {
+        format!(
+            "is_moderated: {}, is_hidden: {}, content_action: {:?}, moderation_reason: {:?}",
+            update.is_moderated, update.is_hidden, update.content_action, update.moderation_reason
+        )
+    })
+}
+
+fn rule_snapshot(rule_id: u64) -> Option<String> {
+    MODERATION_RULES.with(|rules| rules.borrow().get(&rule_id)).map(|rule| format!("{:?}", rule))
+}
+
+fn user_snapshot(principal: Principal) -> Option<String> {
+    USERS.with(|users| users.borrow().get(&principal)).map(|user| {
+        format!(
+            "is_verified: {}, warning_count: {}, is_suspended: {}, suspension_until: {:?}",
+            user.is_verified, user.warning_count, user.is_suspended, user.suspension_until
+        )
+    })
+}
+
+fn role_of(principal: &Principal) -> Role {
+    ROLES.with(|roles| roles.borrow().get(principal)).unwrap_or(Role::User)
+}
+
+fn require_role(required: Role) -> Result<(), String> {
+    let role = role_of(&ic_cdk::caller());
+    if role.rank() >= required.rank() {
+        Ok(())
+    } else {
+        Err(format!("Caller has role {:?} but {:?} is required", role, required))
+    }
+}
+
+// Guards for privileged endpoints
+fn is_owner() -> Result<(), String> {
+    require_role(Role::Owner)
+}
+
+fn is_admin() -> Result<(), String> {
+    require_role(Role::Admin)
+}
+
+fn is_moderator() -> Result<(), String> {
+    require_role(Role::Moderator)
+}
+
+// The principal installing the canister becomes its owner
+fn bootstrap_owner() {
+    let has_owner = ROLES.with(|roles| roles.borrow().iter().any(|(_, role)| role == Role::Owner));
+    if !has_owner {
+        ROLES.with(|roles| roles.borrow_mut().insert(ic_cdk::caller(), Role::Owner));
+    }
+}
+
 // AI Content Analysis function
 fn analyze_content_ai(content: &str) -> AIInsights {
     let lower_content = content.to_lowercase();
@@ -207,7 +1434,7 @@ fn analyze_content_ai(content: &str) -> AIInsights {
     
     // Length analysis
     let word_count = content.split_whitespace().count();
-    if word_count >= 10 && word_count <= 50 {
+    if (10..=50).contains(&word_count) {
         content_score += 20; // Optimal length
     } else if word_count > 50 {
         content_score += 10; // Good length but might be too long
@@ -264,7 +1491,7 @@ fn analyze_content_ai(content: &str) -> AIInsights {
             sentiment_score -= 0.1;
         }
     }
-    sentiment_score = sentiment_score.max(-1.0).min(1.0);
+    sentiment_score = sentiment_score.clamp(-1.0, 1.0);
     
     // Content category detection
     let content_category = if lower_content.contains("work") || lower_content.contains("job") || lower_content.contains("career") {
@@ -360,74 +1587,991 @@ fn analyze_content_ai(content: &str) -> AIInsights {
     }
 }
 
-// Content filtering function using basic keyword detection
-fn filter_content(content: &str) -> (bool, Option<String>, ModerationSeverity) {
-    let lower_content = content.to_lowercase();
-    
-    // Define harmful keywords and their severity levels
-    let harmful_keywords = vec![
-        ("hate", ModerationSeverity::High),
-        ("violence", ModerationSeverity::High),
-        ("harassment", ModerationSeverity::High),
-        ("bully", ModerationSeverity::Medium),
-        ("spam", ModerationSeverity::Low),
-        ("scam", ModerationSeverity::Medium),
-        ("fake", ModerationSeverity::Low),
-        ("misinformation", ModerationSeverity::Medium),
-    ];
-    
-    for (keyword, severity) in harmful_keywords {
-        if lower_content.contains(keyword) {
-            return (true, Some(format!("Content contains inappropriate keyword: {}", keyword)), severity);
-        }
+// Content as the moderation rules see it: case folded, stripped of diacritics,
+// zero-width characters and look-alike letters, with leetspeak undone
+struct NormalizedText {
+    // Words in their original order
+    tokens: Vec<String>,
+    // Same words with spaced-out letters ("h a t e") joined back together
+    collapsed: Vec<String>,
+    // Each run of spaced-out letters on its own; a run may start with real
+    // one-letter words ("I h a t e"), so words are looked for anywhere in it
+    letter_runs: Vec<String>,
+}
+
+impl NormalizedText {
+    fn token_lists(&self) -> [&Vec<String>; 2] {
+        [&self.tokens, &self.collapsed]
     }
-    
-    // Check for excessive caps (shouting)
-    let caps_count = content.chars().filter(|c| c.is_uppercase()).count();
-    let total_chars = content.chars().filter(|c| c.is_alphabetic()).count();
-    
-    if total_chars > 10 && caps_count as f64 / total_chars as f64 > 0.7 {
-        return (true, Some("Content appears to be shouting (excessive caps)".to_string()), ModerationSeverity::Low);
+}
+
+// Maps letters from other scripts that render like Latin ones
+fn fold_confusable(c: char) -> char {
+    match c {
+        'а' | 'α' => 'a',
+        'в' | 'β' => 'b',
+        'с' | 'ϲ' => 'c',
+        'ԁ' | 'đ' => 'd',
+        'е' | 'ε' => 'e',
+        'ɡ' => 'g',
+        'н' => 'h',
+        'і' | 'ι' | 'ı' => 'i',
+        'ј' => 'j',
+        'к' | 'κ' => 'k',
+        'ł' => 'l',
+        'м' => 'm',
+        'η' => 'n',
+        'о' | 'ο' | 'ø' => 'o',
+        'р' | 'ρ' => 'p',
+        'ѕ' => 's',
+        'т' | 'τ' => 't',
+        'υ' => 'u',
+        'ν' => 'v',
+        'ω' => 'w',
+        'х' | 'χ' => 'x',
+        'у' => 'y',
+        _ => c,
     }
-    
-    // Check for repetitive characters
-    let mut prev_char = ' ';
-    let mut repeat_count = 0;
-    for c in content.chars() {
-        if c == prev_char && c != ' ' {
-            repeat_count += 1;
-            if repeat_count > 3 {
-                return (true, Some("Content contains excessive repetitive characters".to_string()), ModerationSeverity::Low);
-            }
-        } else {
-            repeat_count = 0;
-        }
-        prev_char = c;
+}
+
+fn undo_leetspeak(c: char) -> char {
+    match c {
+        '4' | '@' => 'a',
+        '8' => 'b',
+        '3' => 'e',
+        '6' => 'g',
+        '1' | '!' | '|' => 'i',
+        '0' => 'o',
+        '5' | '$' => 's',
+        '7' | '+' => 't',
+        _ => c,
     }
-    
-    (false, None, ModerationSeverity::Low)
 }
 
-#[update()]
-fn post_update(content: String) -> u64 {
-    let caller = ic_cdk::caller();
-    
-    // Check if user is suspended
-    let user = USERS.with(|users| users.borrow().get(&caller).clone());
-    let mut user_data = user.clone();
-    
-    if let Some(ref user_info) = user_data {
-        if user_info.is_suspended {
-            if let Some(suspension_until) = user_info.suspension_until {
-                if time() < suspension_until {
-                    return 0; // User is suspended
-                }
+fn is_leet_symbol(c: char) -> bool {
+    matches!(c, '@' | '!' | '|' | '$' | '+')
+}
+
+fn normalize_for_moderation(content: &str) -> NormalizedText {
+    // Compatibility decomposition turns fullwidth and stylised letters into plain
+    // ones and splits accented letters so the accents can be dropped
+    let mut folded = String::with_capacity(content.len());
+    for c in content.nfkd() {
+        if is_combining_mark(c) || matches!(c, '\u{00AD}' | '\u{200B}'..='\u{200D}' | '\u{2060}' | '\u{FEFF}') {
+            continue;
+        }
+        for lower in c.to_lowercase() {
+            match lower {
+                'ß' => folded.push_str("ss"),
+                'æ' => folded.push_str("ae"),
+                'œ' => folded.push_str("oe"),
+                other => folded.push(fold_confusable(other)),
             }
         }
     }
     
-    // Content filtering
-    let (is_flagged, reason, severity) = filter_content(&content);
+    let mut tokens = Vec::new();
+    for chunk in folded.split_whitespace() {
+        // Mentions, hashtags and trailing punctuation are not part of the word
+        let chunk = chunk.trim_start_matches(['@', '#']).trim_end_matches(|c: char| !c.is_alphanumeric());
+        let chunk = chunk.trim_start_matches(|c: char| !c.is_alphanumeric() && !is_leet_symbol(c));
+        
+        // Only undo leetspeak in words, so plain numbers stay intact
+        let has_letter = chunk.chars().any(|c| c.is_alphabetic());
+        let word: String = chunk.chars()
+            .map(|c| if has_letter { undo_leetspeak(c) } else { c })
+            .collect();
+        tokens.extend(word.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).map(str::to_string));
+    }
+    
+    // Runs of three or more single letters are most likely one spaced-out word
+    let mut collapsed = Vec::new();
+    let mut letter_runs = Vec::new();
+    let mut run = String::new();
+    for token in &tokens {
+        if token.chars().count() == 1 {
+            run.push_str(token);
+            continue;
+        }
+        flush_letter_run(&mut run, &mut collapsed, &mut letter_runs);
+        collapsed.push(token.clone());
+    }
+    flush_letter_run(&mut run, &mut collapsed, &mut letter_runs);
+    
+    NormalizedText { tokens, collapsed, letter_runs }
+}
+
+fn flush_letter_run(run: &mut String, collapsed: &mut Vec<String>, letter_runs: &mut Vec<String>) {
+    if run.chars().count() >= 3 {
+        letter_runs.push(run.clone());
+        collapsed.push(std::mem::take(run));
+    } else {
+        collapsed.extend(run.chars().map(String::from));
+        run.clear();
+    }
+}
+
+fn rule_matches(rule: &ModerationRule, content: &str, normalized: &NormalizedText) -> bool {
+    match rule.match_mode {
+        MatchMode::ExactWord => {
+            let pattern_words = normalize_for_moderation(&rule.pattern).tokens;
+            let spelled_out = pattern_words.concat();
+            !pattern_words.is_empty() && (normalized.token_lists().iter().any(|tokens| {
+                tokens.windows(pattern_words.len()).any(|window| window == pattern_words.as_slice())
+            }) || normalized.letter_runs.iter().any(|run| run.contains(&spelled_out)))
+        }
+        MatchMode::Substring => {
+            let pattern = normalize_for_moderation(&rule.pattern).tokens.join(" ");
+            !pattern.is_empty() && normalized.token_lists().iter().any(|tokens| tokens.join(" ").contains(&pattern))
+        }
+        // Regexes see both the raw and the normalized text
+        MatchMode::Regex => regex_lite::Regex::new(&format!("(?i){}", rule.pattern))
+            .map(|regex| {
+                regex.is_match(content) || normalized.token_lists().iter().any(|tokens| regex.is_match(&tokens.join(" ")))
+            })
+            .unwrap_or(false),
+    }
+}
+
+// Content filtering function evaluating the stored moderation rules.
+// The most restrictive verdict wins, ties go to the higher severity.
+fn filter_content(content: &str) -> Option<ModerationVerdict> {
+    let mut verdicts = rule_verdicts(content);
+    verdicts.extend(filter_content_heuristics(content));
+    strongest_verdict(verdicts)
+}
+
+fn strongest_verdict(verdicts: Vec<ModerationVerdict>) -> Option<ModerationVerdict> {
+    verdicts.into_iter().max_by(|a, b| (a.action, &a.severity).cmp(&(b.action, &b.severity)))
+}
+
+// Verdicts from the stored rules alone, without the heuristics meant for posts
+fn rule_verdicts(content: &str) -> Vec<ModerationVerdict> {
+    let normalized = normalize_for_moderation(content);
+    MODERATION_RULES.with(|rules| {
+        rules.borrow().iter()
+            .filter(|(_, rule)| rule.enabled && rule_matches(rule, content, &normalized))
+            .map(|(id, rule)| ModerationVerdict {
+                action: rule.action,
+                reason: format!("Content matched moderation rule #{}: {}", id, rule.pattern),
+                severity: rule.severity,
+                rule_id: Some(id),
+            })
+            .collect()
+    })
+}
+
+fn filter_content_heuristics(content: &str) -> Option<ModerationVerdict> {
+    let heuristic = |reason: &str| Some(ModerationVerdict {
+        action: RuleAction::Hide,
+        reason: reason.to_string(),
+        severity: ModerationSeverity::Low,
+        rule_id: None,
+    });
+    
+    // Check for excessive caps (shouting)
+    let caps_count = content.chars().filter(|c| c.is_uppercase()).count();
+    let total_chars = content.chars().filter(|c| c.is_alphabetic()).count();
+    
+    if total_chars > 10 && caps_count as f64 / total_chars as f64 > 0.7 {
+        return heuristic("Content appears to be shouting (excessive caps)");
+    }
+    
+    // Check for repetitive characters
+    let mut prev_char = ' ';
+    let mut repeat_count = 0;
+    for c in content.chars() {
+        if c == prev_char && c != ' ' {
+            repeat_count += 1;
+            if repeat_count > 3 {
+                return heuristic("Content contains excessive repetitive characters");
+            }
+        } else {
+            repeat_count = 0;
+        }
+        prev_char = c;
+    }
+    
+    None
+}
+
+// Inserts a flag under a timestamp-based ID that is not yet taken
+fn insert_flag(flag: ModerationFlag) -> u64 {
+    MODERATION_FLAGS.with(|flags| {
+        let mut flags_map = flags.borrow_mut();
+        let mut flag_id = time();
+        while flags_map.contains_key(&flag_id) {
+            flag_id += 1;
+        }
+        flags_map.insert(flag_id, flag);
+        flag_id
+    })
+}
+
+// Seeds the rule set that used to be hardcoded in filter_content
+fn seed_default_rules() {
+    if NEXT_RULE_ID.with(|next_id| *next_id.borrow().get()) > 0 {
+        return;
+    }
+    
+    let defaults = vec![
+        ("hate", ModerationSeverity::High),
+        ("violence", ModerationSeverity::High),
+        ("harassment", ModerationSeverity::High),
+        ("bully", ModerationSeverity::Medium),
+        ("spam", ModerationSeverity::Low),
+        ("scam", ModerationSeverity::Medium),
+        ("fake", ModerationSeverity::Low),
+        ("misinformation", ModerationSeverity::Medium),
+    ];
+    for (pattern, severity) in defaults {
+        store_rule(None, ModerationRuleInput {
+            pattern: pattern.to_string(),
+            match_mode: MatchMode::ExactWord,
+            severity,
+            action: RuleAction::Hide,
+            enabled: true,
+        }, ic_cdk::id());
+    }
+}
+
+fn validate_rule(input: &ModerationRuleInput) -> Result<(), Error> {
+    if input.pattern.trim().is_empty() || input.pattern.chars().count() > 200 {
+        return Err(Error::InvalidArgument("Pattern must be between 1 and 200 characters".to_string()));
+    }
+    if input.match_mode == MatchMode::Regex {
+        regex_lite::Regex::new(&input.pattern)
+            .map_err(|e| Error::InvalidArgument(format!("Invalid regex: {}", e)))?;
+    }
+    Ok(())
+}
+
+// Creates a rule, or replaces it when an ID is given
+fn store_rule(id: Option<u64>, input: ModerationRuleInput, updated_by: Principal) -> u64 {
+    let id = id.unwrap_or_else(|| NEXT_RULE_ID.with(|next_id| {
+        let id = *next_id.borrow().get();
+        next_id.borrow_mut().set(id + 1).unwrap();
+        id
+    }));
+    
+    MODERATION_RULES.with(|rules| rules.borrow_mut().insert(id, ModerationRule {
+        id,
+        pattern: input.pattern,
+        match_mode: input.match_mode,
+        severity: input.severity,
+        action: input.action,
+        enabled: input.enabled,
+        updated_by,
+        updated_at: time(),
+    }));
+    id
+}
+
+// Suspends a user and keeps a record of why
+fn apply_suspension(principal: Principal, duration_hours: u64, reason: String, source: SuspensionSource) -> bool {
+    let user = USERS.with(|users| users.borrow().get(&principal).clone());
+    
+    if let Some(mut user_data) = user {
+        let now = time();
+        let until = now.saturating_add(duration_hours.saturating_mul(HOUR_NS));
+        user_data.is_suspended = true;
+        user_data.suspension_until = Some(until);
+        USERS.with(|users| users.borrow_mut().insert(principal, user_data));
+        
+        SUSPENSIONS.with(|suspensions| {
+            let mut suspensions_map = suspensions.borrow_mut();
+            let mut current = suspensions_map.get(&principal).unwrap_or(StableVec(vec![])).0;
+            current.push(SuspensionRecord { suspended_at: now, until, reason, source });
+            suspensions_map.insert(principal, StableVec(current));
+        });
+        true
+    } else {
+        false
+    }
+}
+
+fn lift_suspension(principal: Principal) -> bool {
+    let user = USERS.with(|users| users.borrow().get(&principal).clone());
+    
+    if let Some(mut user_data) = user {
+        user_data.is_suspended = false;
+        user_data.suspension_until = None;
+        USERS.with(|users| users.borrow_mut().insert(principal, user_data));
+        true
+    } else {
+        false
+    }
+}
+
+// Lifts suspensions whose end time has passed
+fn lift_expired_suspensions() {
+    let now = time();
+    let expired: Vec<Principal> = USERS.with(|users| {
+        users.borrow().iter()
+            .filter_map(|(principal, user)| match user.suspension_until {
+                Some(until) if user.is_suspended && until <= now => Some(principal),
+                _ => None,
+            })
+            .collect()
+    });
+    for principal in expired {
+        expire_suspension(principal);
+    }
+}
+
+fn expire_suspension(principal: Principal) {
+    let before = user_snapshot(principal);
+    lift_suspension(principal);
+    record_audit(
+        ic_cdk::id(), AuditAction::UnsuspendUser, AuditTarget::User(principal),
+        Some("Suspension ended".to_string()), None, before, user_snapshot(principal),
+    );
+}
+
+// Status check shared by every mutating endpoint
+fn ensure_not_anonymous() -> Result<Principal, Error> {
+    let caller = ic_cdk::caller();
+    if caller == Principal::anonymous() {
+        return Err(Error::Anonymous);
+    }
+    Ok(caller)
+}
+
+fn ensure_registered() -> Result<Principal, Error> {
+    let caller = ensure_not_anonymous()?;
+    if !USERS.with(|users| users.borrow().contains_key(&caller)) {
+        return Err(Error::NotRegistered);
+    }
+    Ok(caller)
+}
+
+fn ensure_caller_can_act() -> Result<Principal, Error> {
+    let caller = ensure_registered()?;
+    let user = USERS.with(|users| users.borrow().get(&caller));
+    
+    if let Some(user_info) = user {
+        if user_info.is_suspended {
+            match user_info.suspension_until {
+                // The sweeper may not have run yet
+                Some(until) if until <= time() => {
+                    expire_suspension(caller);
+                }
+                until => return Err(Error::Suspended { until }),
+            }
+        }
+    }
+    Ok(caller)
+}
+
+// Sliding-window limit per caller and action; moderators are exempt
+fn check_rate_limit(caller: Principal, action: WriteAction) -> Result<(), Error> {
+    if role_of(&caller).rank() >= Role::Moderator.rank() {
+        return Ok(());
+    }
+    let policy = RATE_LIMIT_POLICY.with(|policy| policy.borrow().get().clone());
+    let rule = match policy.rules.iter().find(|rule| rule.action == action) {
+        Some(rule) => rule,
+        None => return Ok(()),
+    };
+    
+    let now = time();
+    let window = rule.window_secs.saturating_mul(1_000_000_000);
+    let restricted = USERS.with(|users| users.borrow().get(&caller)).is_some_and(|user| {
+        user.warning_count > 0
+            || user.joined_at.is_some_and(|joined| now < joined.saturating_add(policy.new_account_hours.saturating_mul(HOUR_NS)))
+    });
+    let limit = if restricted { rule.restricted_max_requests } else { rule.max_requests } as usize;
+    
+    let mut state = RATE_LIMITS.with(|limits| limits.borrow().get(&caller)).unwrap_or_default();
+    state.admit(action, limit, window, now)
+        .map_err(|wait| Error::RateLimited { retry_after_secs: wait.div_ceil(1_000_000_000) })?;
+    RATE_LIMITS.with(|limits| limits.borrow_mut().insert(caller, state));
+    Ok(())
+}
+
+// Applies the strictest enforcement rule the user's recent warnings trigger
+fn evaluate_enforcement(principal: Principal) {
+    let policy = ENFORCEMENT_POLICY.with(|policy| policy.borrow().get().clone());
+    // Expired warnings still count as strikes, retracted ones don't
+    let mut warnings = USER_WARNINGS.with(|warnings| warnings.borrow().get(&principal).unwrap_or(StableVec(vec![])).0);
+    warnings.extend(
+        WARNING_HISTORY.with(|history| history.borrow().get(&principal).unwrap_or(StableVec(vec![])).0)
+            .into_iter()
+            .filter(|entry| entry.outcome == WarningOutcome::Expired)
+            .map(|entry| entry.warning),
+    );
+    let now = time();
+    
+    let triggered = policy.rules.iter()
+        .filter_map(|rule| {
+            let window_start = now.saturating_sub(rule.window_hours.saturating_mul(HOUR_NS));
+            let strikes = warnings.iter()
+                .filter(|w| w.severity >= rule.min_severity && w.timestamp >= window_start)
+                .count() as u32;
+            if strikes >= rule.strike_count { Some((rule, strikes)) } else { None }
+        })
+        .max_by_key(|(rule, _)| rule.suspension_hours);
+    
+    if let Some((rule, strikes)) = triggered {
+        // Don't cut short a suspension that already lasts longer
+        let until = now.saturating_add(rule.suspension_hours.saturating_mul(HOUR_NS));
+        let current_until = USERS.with(|users| users.borrow().get(&principal))
+            .filter(|user| user.is_suspended)
+            .and_then(|user| user.suspension_until);
+        if current_until.is_some_and(|current| current >= until) {
+            return;
+        }
+        
+        let reason = format!(
+            "Automatic enforcement: {} {:?}-or-higher warnings within {} hours",
+            strikes, rule.min_severity, rule.window_hours
+        );
+        let before = user_snapshot(principal);
+        apply_suspension(principal, rule.suspension_hours, reason.clone(), SuspensionSource::Automatic);
+        record_audit(
+            ic_cdk::id(), AuditAction::SuspendUser, AuditTarget::User(principal),
+            Some(reason), Some(rule.min_severity.clone()), before, user_snapshot(principal),
+        );
+    }
+}
+
+// Records a warning against a user and counts it towards their warning_count
+fn add_warning(principal: Principal, warning: Warning) {
+    USER_WARNINGS.with(|warnings| {
+        let mut user_warnings = warnings.borrow_mut();
+        let mut current = user_warnings.get(&principal).unwrap_or(StableVec(vec![])).0;
+        current.push(warning);
+        user_warnings.insert(principal, StableVec(current));
+    });
+
+    USERS.with(|users| {
+        let mut users_map = users.borrow_mut();
+        if let Some(mut user) = users_map.get(&principal) {
+            user.warning_count += 1;
+            users_map.insert(principal, user);
+        }
+    });
+    
+    evaluate_enforcement(principal);
+}
+
+fn archive_warnings(principal: Principal, warnings: Vec<Warning>, outcome: WarningOutcome) {
+    let now = time();
+    WARNING_HISTORY.with(|history| {
+        let mut history_map = history.borrow_mut();
+        let mut current = history_map.get(&principal).unwrap_or(StableVec(vec![])).0;
+        current.extend(warnings.into_iter().map(|warning| HistoricalWarning { warning, outcome, archived_at: now }));
+        history_map.insert(principal, StableVec(current));
+    });
+}
+
+fn decrement_warning_count(principal: Principal, by: u32) {
+    USERS.with(|users| {
+        let mut users_map = users.borrow_mut();
+        if let Some(mut user) = users_map.get(&principal) {
+            user.warning_count = user.warning_count.saturating_sub(by);
+            users_map.insert(principal, user);
+        }
+    });
+}
+
+fn next_warning_id() -> u64 {
+    NEXT_WARNING_ID.with(|next_id| {
+        let id = *next_id.borrow().get();
+        next_id.borrow_mut().set(id + 1).unwrap();
+        id
+    })
+}
+
+// Finds the warning a flag issued, active or expired. Warnings from before
+// flag_id was recorded reused the flag ID as their own ID.
+fn flag_warning_id(principal: Principal, flag_id: u64, update_id: u64) -> Option<u64> {
+    let issued_by = |warning: &Warning| match warning.flag_id {
+        Some(id) => id == flag_id,
+        None => warning.id == flag_id && warning.update_id.is_none_or(|id| id == update_id),
+    };
+    let active = USER_WARNINGS.with(|warnings| warnings.borrow().get(&principal))
+        .and_then(|warnings| warnings.0.into_iter().find(|w| issued_by(w)));
+    active.or_else(|| {
+        WARNING_HISTORY.with(|history| history.borrow().get(&principal))
+            .and_then(|history| history.0.into_iter()
+                .find(|entry| entry.outcome == WarningOutcome::Expired && issued_by(&entry.warning)))
+            .map(|entry| entry.warning)
+    }).map(|warning| warning.id)
+}
+
+// Moves a warning to history as retracted, undoing its warning_count increment
+// if it was still active
+fn retract_warning(principal: Principal, warning_id: u64) -> Option<Warning> {
+    let removed = USER_WARNINGS.with(|warnings| {
+        let mut user_warnings = warnings.borrow_mut();
+        let mut current = user_warnings.get(&principal)?.0;
+        let index = current.iter().position(|w| w.id == warning_id)?;
+        let removed = current.remove(index);
+        user_warnings.insert(principal, StableVec(current));
+        Some(removed)
+    });
+
+    if let Some(warning) = removed {
+        decrement_warning_count(principal, 1);
+        archive_warnings(principal, vec![warning.clone()], WarningOutcome::Retracted);
+        return Some(warning);
+    }
+
+    // Expired warnings are already out of warning_count but still count as strikes
+    WARNING_HISTORY.with(|history| {
+        let mut history_map = history.borrow_mut();
+        let mut current = history_map.get(&principal)?.0;
+        let entry = current.iter_mut()
+            .find(|entry| entry.warning.id == warning_id && entry.outcome == WarningOutcome::Expired)?;
+        entry.outcome = WarningOutcome::Retracted;
+        entry.archived_at = time();
+        let warning = entry.warning.clone();
+        history_map.insert(principal, StableVec(current));
+        Some(warning)
+    })
+}
+
+// Moves expired warnings to history and lowers warning counts accordingly
+fn sweep_expired_warnings() {
+    let now = time();
+    let principals: Vec<Principal> = USER_WARNINGS.with(|warnings| warnings.borrow().iter().map(|(p, _)| p).collect());
+    
+    for principal in principals {
+        let current = USER_WARNINGS.with(|warnings| warnings.borrow().get(&principal).unwrap_or(StableVec(vec![])).0);
+        let (active, expired): (Vec<Warning>, Vec<Warning>) = current.into_iter().partition(|w| w.is_active(now));
+        if expired.is_empty() {
+            continue;
+        }
+        
+        let expired_count = expired.len() as u32;
+        USER_WARNINGS.with(|warnings| warnings.borrow_mut().insert(principal, StableVec(active)));
+        archive_warnings(principal, expired, WarningOutcome::Expired);
+        decrement_warning_count(principal, expired_count);
+    }
+}
+
+fn start_timers() {
+    ic_cdk_timers::set_timer_interval(WARNING_SWEEP_INTERVAL, sweep_expired_warnings);
+    ic_cdk_timers::set_timer_interval(SUSPENSION_SWEEP_INTERVAL, lift_expired_suspensions);
+    ic_cdk_timers::set_timer_interval(DELETION_SWEEP_INTERVAL, purge_due_accounts);
+}
+
+fn apply_moderation(update_id: u64, reason: String, action: ContentAction) -> bool {
+    let update = UPDATES.with(|updates| updates.borrow().get(&update_id).clone());
+    
+    if let Some(mut update_data) = update {
+        update_data.is_moderated = true;
+        update_data.moderation_reason = Some(reason);
+        update_data.content_action = Some(action);
+        update_data.is_hidden = action >= ContentAction::Hide;
+        UPDATES.with(|updates| updates.borrow_mut().insert(update_id, update_data));
+        propagate_moderation(update_id, action.restricts_sharing());
+        return true;
+    }
+    false
+}
+
+fn clear_moderation(update_id: u64) -> bool {
+    let update = UPDATES.with(|updates| updates.borrow().get(&update_id).clone());
+    
+    if let Some(mut update_data) = update {
+        update_data.is_moderated = false;
+        update_data.moderation_reason = None;
+        update_data.is_hidden = false; // Unhide the update
+        update_data.moderation_rule_id = None;
+        update_data.content_action = None;
+        UPDATES.with(|updates| updates.borrow_mut().insert(update_id, update_data));
+        propagate_moderation(update_id, false);
+        return true;
+    }
+    false
+}
+
+// Reposts copy the original's content and quotes embed it, so they follow the
+// original's hidden state. Hiding a repost this way leaves is_moderated unset,
+// which is how it is told apart from a repost moderated in its own right.
+fn propagate_moderation(original_id: u64, hidden: bool) {
+    let original_content = UPDATES.with(|updates| updates.borrow().get(&original_id)).map(|u| u.content);
+    let derived: Vec<Update> = UPDATES.with(|updates| {
+        updates.borrow().iter()
+            .filter_map(|(_, update)| if update.original_post_id == Some(original_id) { Some(update) } else { None })
+            .collect()
+    });
+    
+    for mut update in derived {
+        if update.is_quote() {
+            // Only the embedded copy goes, the quote's own text stays up
+            update.quote_content = if hidden { None } else { original_content.clone() };
+        } else if update.is_moderated {
+            continue;
+        } else {
+            update.is_hidden = hidden;
+            update.moderation_reason = if hidden { Some("Original update was moderated".to_string()) } else { None };
+        }
+        UPDATES.with(|updates| updates.borrow_mut().insert(update.id, update));
+    }
+}
+
+fn record_report(update_id: u64, entry: ReportEntry) {
+    let mut summary = REPORT_SUMMARIES.with(|summaries| summaries.borrow().get(&update_id))
+        .unwrap_or(ReportSummary {
+            update_id, reports: vec![], score: 0.0, auto_hidden: false,
+            auto_hide_reason: None, prior_action: None, prior_reason: None,
+        });
+    summary.score += entry.weight;
+    summary.reports.push(entry);
+    
+    let thresholds = REPORT_THRESHOLDS.with(|thresholds| thresholds.borrow().get().clone());
+    // Ignored reporters carry no weight and don't count towards the quorum
+    let reporters = summary.reports.iter().filter(|entry| entry.weight > 0.0).count();
+    if !summary.auto_hidden
+        && reporters as u32 >= thresholds.min_reporters
+        && summary.score >= thresholds.min_score
+    {
+        let reason = format!("Hidden pending review after reports from {} users", reporters);
+        let before = update_snapshot(update_id);
+        let prior = UPDATES.with(|updates| updates.borrow().get(&update_id));
+        summary.prior_action = prior.as_ref().and_then(|update| update.effective_action());
+        summary.prior_reason = prior.and_then(|update| update.moderation_reason);
+        summary.auto_hidden = apply_moderation(update_id, reason.clone(), ContentAction::Hide);
+        summary.auto_hide_reason = Some(reason.clone());
+        record_audit(
+            ic_cdk::id(), AuditAction::AutoHideUpdate, AuditTarget::Update(update_id),
+            Some(reason), None, before, update_snapshot(update_id),
+        );
+    }
+    
+    REPORT_SUMMARIES.with(|summaries| summaries.borrow_mut().insert(update_id, summary));
+}
+
+// Drops a report from its update's summary once the flag is resolved
+fn remove_report(update_id: u64, flag_id: u64) {
+    REPORT_SUMMARIES.with(|summaries| {
+        let mut summaries_map = summaries.borrow_mut();
+        if let Some(mut summary) = summaries_map.get(&update_id) {
+            summary.reports.retain(|entry| entry.flag_id != flag_id);
+            summary.score = summary.reports.iter().map(|entry| entry.weight).sum();
+            summaries_map.insert(update_id, summary);
+        }
+    });
+}
+
+// Once every report against an auto-hidden update is dismissed the update goes
+// back to the moderation it had before, unless a moderator has acted on it since
+fn lift_auto_hide(update_id: u64, actor: Principal) {
+    let Some(mut summary) = REPORT_SUMMARIES.with(|summaries| summaries.borrow().get(&update_id)) else {
+        return;
+    };
+    if !summary.auto_hidden {
+        return;
+    }
+    let auto_hide_reason = summary.auto_hide_reason.take();
+    let prior_action = summary.prior_action.take();
+    let prior_reason = summary.prior_reason.take();
+    summary.auto_hidden = false;
+    REPORT_SUMMARIES.with(|summaries| summaries.borrow_mut().insert(update_id, summary));
+    
+    // Summaries hidden before the reason was recorded only have the prefix to go by
+    let still_auto_hidden = UPDATES.with(|updates| updates.borrow().get(&update_id)).is_some_and(|update| {
+        update.effective_action() == Some(ContentAction::Hide)
+            && match (&auto_hide_reason, &update.moderation_reason) {
+                (Some(expected), Some(reason)) => reason == expected,
+                (None, Some(reason)) => reason.starts_with("Hidden pending review"),
+                (_, None) => false,
+            }
+    });
+    if !still_auto_hidden {
+        return;
+    }
+    let before = update_snapshot(update_id);
+    match prior_action {
+        Some(action) => {
+            apply_moderation(update_id, prior_reason.unwrap_or_else(|| "Restricted by moderators".to_string()), action);
+        }
+        None => {
+            clear_moderation(update_id);
+        }
+    }
+    record_audit(
+        actor, AuditAction::UnmoderateUpdate, AuditTarget::Update(update_id),
+        Some("All reports were dismissed".to_string()), None, before, update_snapshot(update_id),
+    );
+}
+
+// Reposting or quoting content that has been taken down would bring it back
+fn ensure_shareable(original: &Update) -> Result<(), Error> {
+    if original.deleted_at.is_some() || original.effective_action().is_some_and(|action| action.restricts_sharing()) {
+        return Err(Error::UpdateUnavailable);
+    }
+    Ok(())
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, byte| (hash ^ *byte as u64).wrapping_mul(FNV_PRIME))
+}
+
+// Charikar's SimHash over words and word pairs; texts that differ by a word
+// or two end up only a few bits apart
+fn simhash(tokens: &[String]) -> u64 {
+    let mut weights = [0i32; 64];
+    let features = tokens.iter()
+        .map(|token| fnv1a(token.as_bytes()))
+        .chain(tokens.windows(2).map(|pair| fnv1a(format!("{} {}", pair[0], pair[1]).as_bytes())));
+    for hash in features {
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if (hash >> bit) & 1 == 1 { *weight += 1 } else { *weight -= 1 }
+        }
+    }
+    weights.iter().enumerate()
+        .filter(|(_, weight)| **weight > 0)
+        .fold(0, |acc, (bit, _)| acc | (1 << bit))
+}
+
+fn fingerprint(author: Principal, content: &str, timestamp: u64) -> ContentFingerprint {
+    let tokens = normalize_for_moderation(content).tokens;
+    ContentFingerprint {
+        author,
+        exact_hash: fnv1a(tokens.join(" ").as_bytes()),
+        simhash: simhash(&tokens),
+        token_count: tokens.len() as u32,
+        timestamp,
+    }
+}
+
+// Emoji or punctuation-only posts have no tokens and would all hash alike
+fn is_near_duplicate(a: &ContentFingerprint, b: &ContentFingerprint, policy: &SpamPolicy) -> bool {
+    if a.token_count == 0 || b.token_count == 0 {
+        return false;
+    }
+    a.exact_hash == b.exact_hash
+        || (a.token_count >= policy.min_tokens
+            && b.token_count >= policy.min_tokens
+            && (a.simhash ^ b.simhash).count_ones() <= policy.max_hamming_distance)
+}
+
+// Rejects repeats of the caller's own recent updates and flags text that many
+// other accounts posted recently
+fn check_duplicates(candidate: &ContentFingerprint) -> Result<Option<ModerationVerdict>, Error> {
+    let policy = SPAM_POLICY.with(|policy| policy.borrow().get().clone());
+    let cutoff = time().saturating_sub(policy.window_hours.saturating_mul(HOUR_NS));
+    
+    let mut self_duplicates = 0;
+    let mut other_authors: Vec<Principal> = vec![];
+    FINGERPRINTS.with(|fingerprints| {
+        let mut fingerprints = fingerprints.borrow_mut();
+        // Update IDs grow with time, so everything outside the window is at the front
+        let stale: Vec<u64> = fingerprints.iter()
+            .take_while(|(_, fp)| fp.timestamp < cutoff)
+            .map(|(id, _)| id)
+            .collect();
+        for id in stale {
+            fingerprints.remove(&id);
+        }
+        
+        for (_, other) in fingerprints.iter().filter(|(_, other)| is_near_duplicate(candidate, other, &policy)) {
+            if other.author == candidate.author {
+                self_duplicates += 1;
+            } else if !other_authors.contains(&other.author) {
+                other_authors.push(other.author);
+            }
+        }
+    });
+    
+    if self_duplicates >= policy.max_self_duplicates {
+        return Err(Error::ContentRejected { reason: "Duplicates your recent updates".to_string(), rule_id: None });
+    }
+    if candidate.token_count >= policy.min_tokens && other_authors.len() as u32 >= policy.cross_account_threshold {
+        return Ok(Some(ModerationVerdict {
+            action: RuleAction::Flag,
+            reason: format!("Near-duplicate of recent updates from {} other accounts", other_authors.len()),
+            severity: ModerationSeverity::Medium,
+            rule_id: None,
+        }));
+    }
+    Ok(None)
+}
+
+fn strictest(a: Option<ModerationVerdict>, b: Option<ModerationVerdict>) -> Option<ModerationVerdict> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if (b.action, &b.severity) > (a.action, &a.severity) { b } else { a }),
+        (a, b) => a.or(b),
+    }
+}
+
+// Runs content through moderation, failing if a rule rejects it outright
+fn screen_content(content: &str) -> Result<Option<ModerationVerdict>, Error> {
+    let verdict = filter_content(content);
+    if let Some(ModerationVerdict { action: RuleAction::Reject, reason, rule_id, .. }) = verdict {
+        return Err(Error::ContentRejected { reason, rule_id });
+    }
+    Ok(verdict)
+}
+
+// Follow-up for a stored update that moderation hid or flagged
+fn record_verdict(author: Principal, update_id: u64, verdict: Option<ModerationVerdict>) {
+    match verdict {
+        // If content is hidden, add warning to user
+        Some(verdict) if verdict.action == RuleAction::Hide => {
+            record_audit(
+                ic_cdk::id(), AuditAction::AutoHideUpdate, AuditTarget::Update(update_id),
+                Some(verdict.reason.clone()), Some(verdict.severity.clone()), None, update_snapshot(update_id),
+            );
+            add_warning(author, Warning {
+                id: next_warning_id(),
+                reason: verdict.reason,
+                severity: verdict.severity,
+                timestamp: time(),
+                expires_at: Some(time() + 86_400_000_000_000), // 24 hours
+                update_id: Some(update_id),
+                flag_id: None,
+            });
+        }
+        // Flagged content stays visible until a moderator reviews it
+        Some(verdict) => {
+            insert_flag(ModerationFlag {
+                update_id,
+                flagged_by: ic_cdk::id(),
+                reason: verdict.reason,
+                severity: verdict.severity,
+                timestamp: time(),
+                resolution: None,
+            });
+        }
+        None => {}
+    }
+}
+
+fn reporter_stats(reporter: Principal) -> ReporterStats {
+    REPORTER_STATS.with(|stats| stats.borrow().get(&reporter)).unwrap_or_default()
+}
+
+fn record_review(reporter: Principal, outcome: FlagOutcome) {
+    // Flags raised by the rule engine have no reporter to score
+    if reporter == ic_cdk::id() {
+        return;
+    }
+    let mut stats = reporter_stats(reporter);
+    match outcome {
+        FlagOutcome::Dismissed => stats.dismissed += 1,
+        FlagOutcome::ContentRemoved | FlagOutcome::AuthorWarned => stats.confirmed += 1,
+        FlagOutcome::Escalated => return,
+    }
+    REPORTER_STATS.with(|all| all.borrow_mut().insert(reporter, stats));
+}
+
+fn validate_labels(mut labels: Vec<ContentLabel>) -> Result<Vec<ContentLabel>, Error> {
+    labels.sort();
+    labels.dedup();
+    if labels.len() > ContentLabel::ALL.len() {
+        return Err(Error::InvalidArgument("Too many labels".to_string()));
+    }
+    Ok(labels)
+}
+
+fn viewer_preferences(viewer: Principal) -> Vec<(ContentLabel, LabelPreference)> {
+    let stored = USERS.with(|users| users.borrow().get(&viewer))
+        .and_then(|user| user.content_preferences)
+        .unwrap_or_default();
+    ContentLabel::ALL.iter()
+        .map(|label| {
+            let preference = stored.iter()
+                .find(|(l, _)| l == label)
+                .map_or(label.default_preference(), |(_, p)| *p);
+            (*label, preference)
+        })
+        .collect()
+}
+
+fn preference_of(label: ContentLabel, preferences: &[(ContentLabel, LabelPreference)]) -> LabelPreference {
+    preferences.iter().find(|(l, _)| *l == label).map_or(label.default_preference(), |(_, p)| *p)
+}
+
+fn is_following(follower: Principal, followee: Principal) -> bool {
+    FOLLOWS.with(|follows| follows.borrow().get(&follower)).is_some_and(|followed| followed.0.contains(&followee))
+}
+
+fn is_private_account(principal: Principal) -> bool {
+    USERS.with(|users| users.borrow().get(&principal)).is_some_and(|user| user.is_private.unwrap_or(false))
+}
+
+fn add_follow(follower: Principal, followee: Principal) {
+    FOLLOWS.with(|follows| {
+        let mut follows_map = follows.borrow_mut();
+        let mut current = follows_map.get(&follower).unwrap_or(StableVec(vec![])).0;
+        if !current.contains(&followee) {
+            current.push(followee);
+        }
+        follows_map.insert(follower, StableVec(current));
+    });
+    FOLLOWERS.with(|followers| {
+        let mut followers_map = followers.borrow_mut();
+        let mut current = followers_map.get(&followee).unwrap_or(StableVec(vec![])).0;
+        if !current.contains(&follower) {
+            current.push(follower);
+        }
+        followers_map.insert(followee, StableVec(current));
+    });
+}
+
+fn take_follow_request(owner: Principal, requester: Principal) -> Option<FollowRequest> {
+    FOLLOW_REQUESTS.with(|requests| {
+        let mut requests_map = requests.borrow_mut();
+        let mut pending = requests_map.get(&owner)?.0;
+        let index = pending.iter().position(|request| request.requester == requester)?;
+        let request = pending.remove(index);
+        if pending.is_empty() {
+            requests_map.remove(&owner);
+        } else {
+            requests_map.insert(owner, StableVec(pending));
+        }
+        Some(request)
+    })
+}
+
+fn active_claim(update_id: u64) -> Option<QueueClaim> {
+    QUEUE_CLAIMS.with(|claims| claims.borrow().get(&update_id))
+        .filter(|claim| claim.expires_at > time())
+}
+
+fn has_open_flags(update_id: u64) -> bool {
+    MODERATION_FLAGS.with(|flags| {
+        flags.borrow().iter().any(|(_, flag)| flag.update_id == update_id && flag.is_open())
+    })
+}
+
+fn store_claim(update_id: u64, moderator: Principal, assigned_by: Option<Principal>) -> QueueClaim {
+    let now = time();
+    let claim = QueueClaim { moderator, assigned_by, claimed_at: now, expires_at: now + QUEUE_CLAIM_DURATION_NS };
+    QUEUE_CLAIMS.with(|claims| claims.borrow_mut().insert(update_id, claim.clone()));
+    claim
+}
+
+fn warning_history(principal: Principal) -> Vec<HistoricalWarning> {
+    let now = time();
+    let mut history = WARNING_HISTORY.with(|history| history.borrow().get(&principal).unwrap_or(StableVec(vec![])).0);
+    let unswept = USER_WARNINGS.with(|warnings| warnings.borrow().get(&principal).unwrap_or(StableVec(vec![])).0)
+        .into_iter()
+        .filter(|w| !w.is_active(now))
+        .map(|warning| HistoricalWarning {
+            archived_at: warning.expires_at.unwrap_or(now),
+            warning,
+            outcome: WarningOutcome::Expired,
+        });
+    history.extend(unswept);
+    history
+}
+
+#[update()]
+fn post_update(content: String, labels: Option<Vec<ContentLabel>>) -> Result<u64, Error> {
+    let caller = ensure_caller_can_act()?;
+    check_rate_limit(caller, WriteAction::PostUpdate)?;
+    let labels = validate_labels(labels.unwrap_or_default())?;
+    
+    // Content filtering
+    let fingerprint = fingerprint(caller, &content, time());
+    let verdict = strictest(screen_content(&content)?, check_duplicates(&fingerprint)?);
+    let hidden_by = verdict.clone().filter(|v| v.action == RuleAction::Hide);
     
     let id = NEXT_UPDATE_ID.with(|next_id| {
         let id = *next_id.borrow().get();
@@ -435,71 +2579,642 @@ fn post_update(content: String) -> u64 {
         id
     });
     
-    let update = Update {
-        id,
-        author: caller,
-        content,
-        timestamp: time(),
-        likes: 0,
-        reposts: 0,
-        quotes: 0,
-        original_post_id: None,
-        quote_content: None,
-        is_moderated: is_flagged,
-        moderation_reason: reason.clone(),
-        is_hidden: is_flagged,
+    let update = Update {
+        id,
+        author: caller,
+        content,
+        timestamp: time(),
+        likes: 0,
+        reposts: 0,
+        quotes: 0,
+        original_post_id: None,
+        quote_content: None,
+        is_moderated: hidden_by.is_some(),
+        moderation_reason: hidden_by.as_ref().map(|v| v.reason.clone()),
+        is_hidden: hidden_by.is_some(),
+        moderation_rule_id: hidden_by.as_ref().and_then(|v| v.rule_id),
+        content_action: hidden_by.as_ref().map(|_| ContentAction::Hide),
+        author_labels: Some(labels),
+        moderator_labels: None,
+        deleted_at: None,
+    };
+    
+    UPDATES.with(|updates| updates.borrow_mut().insert(id, update));
+    FINGERPRINTS.with(|fingerprints| fingerprints.borrow_mut().insert(id, fingerprint));
+    record_verdict(caller, id, verdict);
+    
+    Ok(id)
+}
+
+fn validate_handle(handle: &str) -> Result<(), Error> {
+    let length = handle.chars().count();
+    if !(MIN_HANDLE_LENGTH..=MAX_HANDLE_LENGTH).contains(&length) {
+        return Err(Error::InvalidArgument(format!(
+            "Handle must be between {} and {} characters", MIN_HANDLE_LENGTH, MAX_HANDLE_LENGTH
+        )));
+    }
+    if !handle.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(Error::InvalidArgument("Handle may only contain letters, digits and underscores".to_string()));
+    }
+    if RESERVED_HANDLES.contains(&handle.to_lowercase().as_str()) {
+        return Err(Error::InvalidArgument("Handle is reserved".to_string()));
+    }
+    Ok(())
+}
+
+// Canisters upgraded from before the index existed build it from USERS once.
+// Where existing handles collide case-insensitively the first principal keeps
+// the index entry; the others still show their handle but can't be looked up by it.
+fn index_existing_handles() {
+    if !HANDLES.with(|handles| handles.borrow().is_empty()) {
+        return;
+    }
+    let users: Vec<(Principal, String)> = USERS.with(|users| {
+        users.borrow().iter().map(|(principal, user)| (principal, user.handle)).collect()
+    });
+    HANDLES.with(|handles| {
+        let mut handles = handles.borrow_mut();
+        for (principal, handle) in users {
+            let key = handle.to_lowercase();
+            if !handles.contains_key(&key) {
+                handles.insert(key, principal);
+            }
+        }
+    });
+}
+
+#[update()]
+fn register(handle: String) -> Result<(), Error> {
+    let caller = ensure_not_anonymous()?;
+    if USERS.with(|users| users.borrow().contains_key(&caller)) {
+        return Err(Error::AlreadyExists);
+    }
+    validate_handle(&handle)?;
+    let key = handle.to_lowercase();
+    ensure_handle_available(&key, caller)?;
+    
+    HANDLES.with(|handles| handles.borrow_mut().insert(key, caller));
+    USERS.with(|users| users.borrow_mut().insert(caller, User { 
+        handle, 
+        is_verified: false, 
+        warning_count: 0, 
+        is_suspended: false, 
+        suspension_until: None,
+        content_preferences: None,
+        joined_at: Some(time()),
+        display_name: None,
+        bio: None,
+        location: None,
+        website: None,
+        avatar_url: None,
+        banner_url: None,
+        is_private: None,
+    }));
+    Ok(())
+}
+
+#[query]
+fn get_user(principal: Principal) -> Option<User> {
+    USERS.with(|users| users.borrow().get(&principal).clone())
+}
+
+fn profile_text(value: Option<String>, field: &str, max_length: usize) -> Result<Option<String>, Error> {
+    let value = match value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty()) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    if value.chars().count() > max_length {
+        return Err(Error::InvalidArgument(format!("{} must be at most {} characters", field, max_length)));
+    }
+    // Profiles are public, so anything the rules would hide is refused outright.
+    // The caps and repetition heuristics are for posts, not names and places
+    if let Some(verdict) = strongest_verdict(rule_verdicts(&value)).filter(|v| v.action >= RuleAction::Hide) {
+        return Err(Error::ContentRejected { reason: verdict.reason, rule_id: verdict.rule_id });
+    }
+    Ok(Some(value))
+}
+
+fn profile_url(value: Option<String>, field: &str) -> Result<Option<String>, Error> {
+    let value = match value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty()) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    if value.len() > MAX_URL_LENGTH || value.chars().any(char::is_whitespace) {
+        return Err(Error::InvalidArgument(format!("{} must be a URL of at most {} characters", field, MAX_URL_LENGTH)));
+    }
+    if !value.starts_with("https://") && !value.starts_with("http://") {
+        return Err(Error::InvalidArgument(format!("{} must start with http:// or https://", field)));
+    }
+    Ok(Some(value))
+}
+
+// Accounts registered before joined_at existed get the time of their first
+// update, or the upgrade time if they never posted
+// Warning IDs used to be timestamps or flag IDs, so the counter starts past
+// every ID already handed out
+fn seed_warning_ids() {
+    if NEXT_WARNING_ID.with(|next_id| *next_id.borrow().get()) != 0 {
+        return;
+    }
+    let active = USER_WARNINGS.with(|warnings| {
+        warnings.borrow().iter().flat_map(|(_, list)| list.0.into_iter().map(|w| w.id)).max()
+    });
+    let archived = WARNING_HISTORY.with(|history| {
+        history.borrow().iter().flat_map(|(_, list)| list.0.into_iter().map(|entry| entry.warning.id)).max()
+    });
+    if let Some(max_id) = active.max(archived) {
+        NEXT_WARNING_ID.with(|next_id| next_id.borrow_mut().set(max_id.saturating_add(1)).unwrap());
+    }
+}
+
+fn backfill_join_dates() {
+    let missing: Vec<Principal> = USERS.with(|users| {
+        users.borrow().iter().filter(|(_, user)| user.joined_at.is_none()).map(|(principal, _)| principal).collect()
+    });
+    if missing.is_empty() {
+        return;
+    }
+    let mut first_update: HashMap<Principal, u64> = HashMap::new();
+    UPDATES.with(|updates| {
+        for (_, update) in updates.borrow().iter() {
+            let earliest = first_update.entry(update.author).or_insert(update.timestamp);
+            *earliest = (*earliest).min(update.timestamp);
+        }
+    });
+    let now = time();
+    USERS.with(|users| {
+        let mut users = users.borrow_mut();
+        for principal in missing {
+            if let Some(mut user) = users.get(&principal) {
+                user.joined_at = Some(first_update.get(&principal).copied().unwrap_or(now));
+                users.insert(principal, user);
+            }
+        }
+    });
+}
+
+#[update()]
+fn update_profile(profile: ProfileInput) -> Result<User, Error> {
+    let caller = ensure_caller_can_act()?;
+    let mut user = USERS.with(|users| users.borrow().get(&caller)).ok_or(Error::NotRegistered)?;
+    
+    user.display_name = profile_text(profile.display_name, "Display name", MAX_DISPLAY_NAME_LENGTH)?;
+    user.bio = profile_text(profile.bio, "Bio", MAX_BIO_LENGTH)?;
+    user.location = profile_text(profile.location, "Location", MAX_LOCATION_LENGTH)?;
+    user.website = profile_url(profile.website, "Website")?;
+    user.avatar_url = profile_url(profile.avatar_url, "Avatar")?;
+    user.banner_url = profile_url(profile.banner_url, "Banner")?;
+    
+    USERS.with(|users| users.borrow_mut().insert(caller, user.clone()));
+    Ok(user)
+}
+
+#[query]
+fn get_user_by_handle(handle: String) -> Option<(Principal, User)> {
+    let key = handle.to_lowercase();
+    // Old handles keep resolving to their account while reserved
+    let principal = HANDLES.with(|handles| handles.borrow().get(&key))
+        .or_else(|| active_reservation(&key).map(|reservation| reservation.owner))?;
+    USERS.with(|users| users.borrow().get(&principal)).map(|user| (principal, user))
+}
+
+fn active_reservation(key: &str) -> Option<HandleReservation> {
+    HANDLE_RESERVATIONS.with(|reservations| reservations.borrow().get(&key.to_string()))
+        .filter(|reservation| reservation.expires_at > time())
+}
+
+// A handle is free if nobody holds it and it isn't reserved for someone else
+fn ensure_handle_available(key: &str, claimant: Principal) -> Result<(), Error> {
+    if HANDLES.with(|handles| handles.borrow().get(&key.to_string())).is_some_and(|owner| owner != claimant) {
+        return Err(Error::HandleTaken);
+    }
+    match HANDLE_RESERVATIONS.with(|reservations| reservations.borrow().get(&key.to_string())) {
+        Some(reservation) if reservation.expires_at > time() && reservation.owner != claimant => Err(Error::HandleTaken),
+        Some(_) => {
+            // Expired, or the previous owner taking it back
+            HANDLE_RESERVATIONS.with(|reservations| reservations.borrow_mut().remove(&key.to_string()));
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+#[update()]
+fn change_handle(new_handle: String) -> Result<(), Error> {
+    let caller = ensure_caller_can_act()?;
+    let mut user = USERS.with(|users| users.borrow().get(&caller)).ok_or(Error::NotRegistered)?;
+    validate_handle(&new_handle)?;
+    if new_handle == user.handle {
+        return Ok(());
+    }
+    
+    let mut history = HANDLE_HISTORY.with(|history| history.borrow().get(&caller)).unwrap_or(StableVec(vec![])).0;
+    let now = time();
+    if let Some(last) = history.last() {
+        if now < last.changed_at + HANDLE_CHANGE_COOLDOWN_NS {
+            return Err(Error::RateLimited { retry_after_secs: (last.changed_at + HANDLE_CHANGE_COOLDOWN_NS - now).div_ceil(1_000_000_000) });
+        }
+    }
+    
+    let old_key = user.handle.to_lowercase();
+    let new_key = new_handle.to_lowercase();
+    // Only the capitalisation changes; nothing to reserve
+    if new_key != old_key {
+        ensure_handle_available(&new_key, caller)?;
+        // Legacy accounts left unindexed over a collision don't own their old key
+        let owns_old_key = HANDLES.with(|handles| handles.borrow().get(&old_key)) == Some(caller);
+        HANDLES.with(|handles| {
+            let mut handles = handles.borrow_mut();
+            if owns_old_key {
+                handles.remove(&old_key);
+            }
+            handles.insert(new_key, caller);
+        });
+        if owns_old_key {
+            HANDLE_RESERVATIONS.with(|reservations| {
+                reservations.borrow_mut().insert(old_key, HandleReservation { owner: caller, expires_at: now + HANDLE_RESERVATION_NS })
+            });
+        }
+    }
+    
+    history.push(HandleChange { old_handle: user.handle.clone(), new_handle: new_handle.clone(), changed_at: now });
+    HANDLE_HISTORY.with(|all| all.borrow_mut().insert(caller, StableVec(history)));
+    user.handle = new_handle;
+    USERS.with(|users| users.borrow_mut().insert(caller, user));
+    Ok(())
+}
+
+#[query]
+fn get_handle_history(principal: Principal) -> Vec<HandleChange> {
+    HANDLE_HISTORY.with(|history| history.borrow().get(&principal)).unwrap_or(StableVec(vec![])).0
+}
+
+fn adjust_update(update_id: u64, change: impl FnOnce(&mut Update)) {
+    UPDATES.with(|updates| {
+        let mut updates = updates.borrow_mut();
+        if let Some(mut update) = updates.get(&update_id) {
+            change(&mut update);
+            updates.insert(update_id, update);
+        }
+    });
+}
+
+fn remove_from_lists<K: Storable + Ord + Clone>(
+    lists: &mut StableBTreeMap<K, StableVec<Principal>, Memory>,
+    principal: Principal,
+) -> Vec<K> {
+    let containing: Vec<K> = lists.iter()
+        .filter(|(_, list)| list.0.contains(&principal))
+        .map(|(key, _)| key)
+        .collect();
+    for key in &containing {
+        if let Some(mut list) = lists.get(key) {
+            list.0.retain(|p| *p != principal);
+            lists.insert(key.clone(), list);
+        }
+    }
+    containing
+}
+
+// Updates moderation may still need: acted on, flagged or under appeal
+fn is_retained(update: &Update, flagged: &HashSet<u64>, appealed: &HashSet<u64>) -> bool {
+    update.is_moderated || update.effective_action().is_some()
+        || flagged.contains(&update.id) || appealed.contains(&update.id)
+}
+
+// Quotes of a deleted update lose the embedded copy; plain reposts are nothing
+// but a copy, so they go entirely unless moderation still needs them
+fn detach_shared_copies(derived: Vec<Update>, flagged: &HashSet<u64>, appealed: &HashSet<u64>, now: u64) {
+    for mut update in derived {
+        if update.is_quote() {
+            update.quote_content = None;
+            UPDATES.with(|updates| updates.borrow_mut().insert(update.id, update));
+        } else if is_retained(&update, flagged, appealed) {
+            update.deleted_at = Some(now);
+            UPDATES.with(|updates| updates.borrow_mut().insert(update.id, update));
+        } else {
+            UPDATES.with(|updates| updates.borrow_mut().remove(&update.id));
+            LIKES.with(|likes| likes.borrow_mut().remove(&update.id));
+            REPOSTS.with(|reposts| reposts.borrow_mut().remove(&update.id));
+            FINGERPRINTS.with(|fingerprints| fingerprints.borrow_mut().remove(&update.id));
+        }
+    }
+}
+
+// Removes the account and everything tied to it. Updates that moderation may
+// still need (moderated, flagged or under appeal) are kept as hidden tombstones,
+// and warnings, suspensions, reports, appeals and the audit log are retained.
+fn purge_account(principal: Principal) {
+    PENDING_DELETIONS.with(|pending| pending.borrow_mut().remove(&principal));
+    let user = match USERS.with(|users| users.borrow_mut().remove(&principal)) {
+        Some(user) => user,
+        None => return,
     };
+    let now = time();
     
-    UPDATES.with(|updates| updates.borrow_mut().insert(id, update));
+    // Follow lists on both sides
+    FOLLOWS.with(|follows| follows.borrow_mut().remove(&principal));
+    FOLLOWERS.with(|followers| followers.borrow_mut().remove(&principal));
+    FOLLOWS.with(|follows| remove_from_lists(&mut follows.borrow_mut(), principal));
+    FOLLOWERS.with(|followers| remove_from_lists(&mut followers.borrow_mut(), principal));
+    FOLLOW_REQUESTS.with(|requests| requests.borrow_mut().remove(&principal));
+    let requested: Vec<Principal> = FOLLOW_REQUESTS.with(|requests| {
+        requests.borrow().iter()
+            .filter(|(_, pending)| pending.0.iter().any(|request| request.requester == principal))
+            .map(|(owner, _)| owner)
+            .collect()
+    });
+    for owner in requested {
+        take_follow_request(owner, principal);
+    }
     
-    // If content is flagged, add warning to user
-    if is_flagged {
-        USER_WARNINGS.with(|warnings| {
-            let mut user_warnings = warnings.borrow_mut();
-            let mut current = user_warnings.get(&caller).unwrap_or(StableVec(vec![])).0;
-            current.push(Warning {
-                id: time(), // Use timestamp as warning ID
-                reason: reason.unwrap_or("Content flagged by automatic moderation".to_string()),
-                severity,
-                timestamp: time(),
-                expires_at: Some(time() + 86400_000_000_000), // 24 hours
+    // Likes on other updates, with their counters
+    for update_id in LIKES.with(|likes| remove_from_lists(&mut likes.borrow_mut(), principal)) {
+        adjust_update(update_id, |update| update.likes = update.likes.saturating_sub(1));
+    }
+    REPOSTS.with(|reposts| remove_from_lists(&mut reposts.borrow_mut(), principal));
+    
+    let flagged: HashSet<u64> = MODERATION_FLAGS.with(|flags| flags.borrow().iter().map(|(_, flag)| flag.update_id).collect());
+    let appealed: HashSet<u64> = APPEALS.with(|appeals| {
+        appeals.borrow().iter()
+            .filter_map(|(_, appeal)| match appeal.target {
+                AppealTarget::Update(update_id) => Some(update_id),
+                AppealTarget::Warning(_) => None,
+            })
+            .collect()
+    });
+    // Collected up front so the purge stays linear in the number of updates
+    let (own, derived): (Vec<Update>, Vec<Update>) = UPDATES.with(|updates| {
+        let updates = updates.borrow();
+        let own: Vec<Update> = updates.iter().filter(|(_, update)| update.author == principal).map(|(_, update)| update).collect();
+        let own_ids: HashSet<u64> = own.iter().map(|update| update.id).collect();
+        let derived = updates.iter()
+            .map(|(_, update)| update)
+            .filter(|update| update.author != principal && update.original_post_id.is_some_and(|id| own_ids.contains(&id)))
+            .collect();
+        (own, derived)
+    });
+    detach_shared_copies(derived, &flagged, &appealed, now);
+    for update in own {
+        if let Some(original_id) = update.original_post_id {
+            let is_quote = update.is_quote();
+            adjust_update(original_id, |original| {
+                if is_quote {
+                    original.quotes = original.quotes.saturating_sub(1);
+                } else {
+                    original.reposts = original.reposts.saturating_sub(1);
+                }
             });
-            user_warnings.insert(caller, StableVec(current));
-        });
+        }
+        FINGERPRINTS.with(|fingerprints| fingerprints.borrow_mut().remove(&update.id));
         
-        // Update user warning count
-        if let Some(ref mut user_info) = user_data {
-            user_info.warning_count += 1;
-            USERS.with(|users| users.borrow_mut().insert(caller, user_info.clone()));
+        if is_retained(&update, &flagged, &appealed) {
+            adjust_update(update.id, |tombstone| tombstone.deleted_at = Some(now));
+        } else {
+            UPDATES.with(|updates| updates.borrow_mut().remove(&update.id));
+            LIKES.with(|likes| likes.borrow_mut().remove(&update.id));
+            REPOSTS.with(|reposts| reposts.borrow_mut().remove(&update.id));
         }
     }
     
-    id
+    if let Some(warnings) = USER_WARNINGS.with(|warnings| warnings.borrow_mut().remove(&principal)) {
+        archive_warnings(principal, warnings.0, WarningOutcome::AccountDeleted);
+    }
+    ROLES.with(|roles| roles.borrow_mut().remove(&principal));
+    REPORTER_STATS.with(|stats| stats.borrow_mut().remove(&principal));
+    RATE_LIMITS.with(|limits| limits.borrow_mut().remove(&principal));
+    HANDLE_HISTORY.with(|history| history.borrow_mut().remove(&principal));
+    
+    // The handle stays reserved for a while so nobody can impersonate the account
+    let key = user.handle.to_lowercase();
+    if HANDLES.with(|handles| handles.borrow().get(&key)) == Some(principal) {
+        HANDLES.with(|handles| handles.borrow_mut().remove(&key));
+        HANDLE_RESERVATIONS.with(|reservations| {
+            reservations.borrow_mut().insert(key, HandleReservation { owner: principal, expires_at: now + HANDLE_RESERVATION_NS })
+        });
+    }
+    
+    record_audit(
+        ic_cdk::id(), AuditAction::DeleteAccount, AuditTarget::User(principal),
+        None, None, Some(format!("handle: {}", user.handle)), None,
+    );
+}
+
+// Each account is purged in its own message, so one that runs out of
+// instructions can't roll back or hold up the others
+fn purge_due_accounts() {
+    let now = time();
+    let due: Vec<Principal> = PENDING_DELETIONS.with(|pending| {
+        pending.borrow().iter()
+            .filter(|(_, request)| request.purge_at <= now)
+            .map(|(principal, _)| principal)
+            .collect()
+    });
+    for principal in due {
+        ic_cdk_timers::set_timer(Duration::ZERO, move || purge_account(principal));
+    }
 }
 
+// Account deletion
 #[update()]
-fn register(handle: String) {
-    let caller = ic_cdk::caller();
-    USERS.with(|users| users.borrow_mut().insert(caller, User { 
-        handle, 
-        is_verified: false, 
-        warning_count: 0, 
-        is_suspended: false, 
-        suspension_until: None 
-    }));
+fn request_account_deletion() -> Result<DeletionRequest, Error> {
+    let caller = ensure_registered()?;
+    // The canister can't be left without an owner
+    if role_of(&caller) == Role::Owner {
+        return Err(Error::InvalidArgument("Transfer ownership before deleting the owner account".to_string()));
+    }
+    if PENDING_DELETIONS.with(|pending| pending.borrow().contains_key(&caller)) {
+        return Err(Error::AlreadyExists);
+    }
+    let now = time();
+    let request = DeletionRequest { requested_at: now, purge_at: now + DELETION_GRACE_PERIOD_NS };
+    PENDING_DELETIONS.with(|pending| pending.borrow_mut().insert(caller, request.clone()));
+    Ok(request)
+}
+
+#[update()]
+fn cancel_account_deletion() -> Result<(), Error> {
+    let caller = ensure_registered()?;
+    PENDING_DELETIONS.with(|pending| pending.borrow_mut().remove(&caller))
+        .map(|_| ())
+        .ok_or(Error::NotFound)
 }
 
 #[query]
-fn get_user(principal: Principal) -> Option<User> {
-    USERS.with(|users| users.borrow().get(&principal).clone())
+fn get_account_deletion() -> Option<DeletionRequest> {
+    PENDING_DELETIONS.with(|pending| pending.borrow().get(&ic_cdk::caller()))
+}
+
+// Data export. Ids and nanosecond timestamps are strings since they
+// don't fit in a JSON number without losing precision
+fn export_account(principal: Principal) -> serde_json::Value {
+    let handle = USERS.with(|users| users.borrow().get(&principal)).map(|user| user.handle);
+    json!({ "principal": principal.to_text(), "handle": handle })
+}
+
+fn export_items(principal: Principal, section: ExportSection) -> Vec<serde_json::Value> {
+    match section {
+        ExportSection::Profile => {
+            let Some(user) = USERS.with(|users| users.borrow().get(&principal)) else {
+                return vec![];
+            };
+            let handle_history: Vec<serde_json::Value> = get_handle_history(principal).into_iter()
+                .map(|change| json!({
+                    "old_handle": change.old_handle,
+                    "new_handle": change.new_handle,
+                    "changed_at": change.changed_at.to_string(),
+                }))
+                .collect();
+            vec![json!({
+                "principal": principal.to_text(),
+                "handle": user.handle,
+                "display_name": user.display_name,
+                "bio": user.bio,
+                "location": user.location,
+                "website": user.website,
+                "avatar_url": user.avatar_url,
+                "banner_url": user.banner_url,
+                "is_verified": user.is_verified,
+                "is_private": user.is_private.unwrap_or(false),
+                "joined_at": user.joined_at.map(|at| at.to_string()),
+                "content_preferences": user.content_preferences.unwrap_or_default(),
+                "handle_history": handle_history,
+            })]
+        }
+        ExportSection::Updates => UPDATES.with(|updates| {
+            updates.borrow().iter()
+                .filter(|(_, update)| update.author == principal && update.deleted_at.is_none())
+                .map(|(id, update)| {
+                    let kind = match update.original_post_id {
+                        None => "post",
+                        Some(_) if update.is_quote() => "quote",
+                        Some(_) => "repost",
+                    };
+                    let moderation = update.effective_action().map(|action| json!({
+                        "action": action,
+                        "reason": update.moderation_reason,
+                    }));
+                    json!({
+                        "id": id.to_string(),
+                        "kind": kind,
+                        "content": update.content,
+                        "created_at": update.timestamp.to_string(),
+                        "original_post_id": update.original_post_id.map(|id| id.to_string()),
+                        "quote_content": update.quote_content,
+                        "likes": update.likes,
+                        "reposts": update.reposts,
+                        "quotes": update.quotes,
+                        "author_labels": update.author_labels.unwrap_or_default(),
+                        "moderator_labels": update.moderator_labels.unwrap_or_default(),
+                        "moderation": moderation,
+                    })
+                })
+                .collect()
+        }),
+        ExportSection::Likes => LIKES.with(|likes| {
+            likes.borrow().iter()
+                .filter(|(_, likers)| likers.0.contains(&principal))
+                .map(|(update_id, _)| json!({ "update_id": update_id.to_string() }))
+                .collect()
+        }),
+        ExportSection::Following => get_following(principal).into_iter().map(export_account).collect(),
+        ExportSection::Followers => get_followers(principal).into_iter().map(export_account).collect(),
+        ExportSection::Warnings => {
+            let export_warning = |warning: Warning, status: &str, archived_at: Option<u64>| json!({
+                "id": warning.id.to_string(),
+                "reason": warning.reason,
+                "severity": warning.severity,
+                "issued_at": warning.timestamp.to_string(),
+                "expires_at": warning.expires_at.map(|at| at.to_string()),
+                "update_id": warning.update_id.map(|id| id.to_string()),
+                "status": status,
+                "archived_at": archived_at.map(|at| at.to_string()),
+            });
+            let mut items: Vec<serde_json::Value> = get_user_warnings(principal).into_iter()
+                .map(|warning| export_warning(warning, "Active", None))
+                .collect();
+            items.extend(warning_history(principal).into_iter().map(|entry| {
+                let status = match entry.outcome {
+                    WarningOutcome::Expired => "Expired",
+                    WarningOutcome::Retracted => "Retracted",
+                    WarningOutcome::AccountDeleted => "AccountDeleted",
+                };
+                export_warning(entry.warning, status, Some(entry.archived_at))
+            }));
+            items
+        }
+        ExportSection::Reports => reports_filed_by(principal).into_iter()
+            .map(|report| json!({
+                "flag_id": report.flag_id.to_string(),
+                "update_id": report.update_id.to_string(),
+                "reason": report.reason,
+                "severity": report.severity,
+                "filed_at": report.filed_at.to_string(),
+                "outcome": report.outcome,
+                "resolved_at": report.resolved_at.map(|at| at.to_string()),
+            }))
+            .collect(),
+    }
+}
+
+// Walks the export one chunk at a time; start without a cursor and keep
+// passing next_cursor back until it comes back empty
+#[query]
+fn export_my_data(cursor: Option<ExportCursor>) -> Result<ExportChunk, Error> {
+    let caller = ensure_registered()?;
+    let cursor = cursor.unwrap_or(ExportCursor { section: ExportSection::Profile, offset: 0 });
+    let items = export_items(caller, cursor.section);
+    export_chunk(&cursor, &items, MAX_EXPORT_CHUNK_BYTES)
+}
+
+fn export_chunk(cursor: &ExportCursor, items: &[serde_json::Value], max_bytes: usize) -> Result<ExportChunk, Error> {
+    let start = cursor.offset as usize;
+    if start > items.len() {
+        return Err(Error::InvalidArgument("Export cursor is past the end of the section".to_string()));
+    }
+    
+    // Always take at least one item so an oversized one can't stall the export
+    let mut end = start;
+    let mut size = 0;
+    while end < items.len() {
+        let item_size = items[end].to_string().len();
+        if end > start && size + item_size > max_bytes {
+            break;
+        }
+        size += item_size;
+        end += 1;
+    }
+    
+    let document = ExportDocument {
+        format_version: EXPORT_FORMAT_VERSION,
+        section: cursor.section,
+        offset: start as u64,
+        total: items.len() as u64,
+        items: &items[start..end],
+    };
+    let next_cursor = if end < items.len() {
+        Some(ExportCursor { section: cursor.section, offset: end as u64 })
+    } else {
+        cursor.section.next().map(|section| ExportCursor { section, offset: 0 })
+    };
+    Ok(ExportChunk {
+        format_version: EXPORT_FORMAT_VERSION,
+        section: cursor.section,
+        data: serde_json::to_string(&document).expect("Export documents are valid JSON"),
+        next_cursor,
+    })
 }
 
 #[update()]
-fn repost_update(original_post_id: u64) -> u64 {
-    let caller = ic_cdk::caller();
-    let original_update = UPDATES.with(|updates| updates.borrow().get(&original_post_id).clone());
+fn repost_update(original_post_id: u64) -> Result<u64, Error> {
+    let caller = ensure_caller_can_act()?;
+    check_rate_limit(caller, WriteAction::RepostUpdate)?;
+    let original_update = UPDATES.with(|updates| updates.borrow().get(&original_post_id).clone())
+        .filter(|original| Viewer::current().can_see(original));
     
     if let Some(original) = original_update {
+        ensure_shareable(&original)?;
+        
         let id = NEXT_UPDATE_ID.with(|next_id| {
             let id = *next_id.borrow().get();
             next_id.borrow_mut().set(id + 1).unwrap();
@@ -519,6 +3234,12 @@ fn repost_update(original_post_id: u64) -> u64 {
             is_moderated: false,
             moderation_reason: None,
             is_hidden: false,
+            moderation_rule_id: None,
+            content_action: None,
+            // Shared content keeps its labels; moderator ones stay out of the sharer's reach
+            author_labels: original.author_labels.clone(),
+            moderator_labels: original.moderator_labels.clone(),
+            deleted_at: None,
         };
         
         UPDATES.with(|updates| updates.borrow_mut().insert(id, repost));
@@ -528,18 +3249,36 @@ fn repost_update(original_post_id: u64) -> u64 {
         original_mut.reposts += 1;
         UPDATES.with(|updates| updates.borrow_mut().insert(original_post_id, original_mut));
         
-        id
+        REPOSTS.with(|reposts| {
+            let mut reposts_map = reposts.borrow_mut();
+            let mut current = reposts_map.get(&original_post_id).unwrap_or(StableVec(vec![])).0;
+            if !current.contains(&caller) {
+                current.push(caller);
+            }
+            reposts_map.insert(original_post_id, StableVec(current));
+        });
+        
+        Ok(id)
     } else {
-        0 // Return 0 if original post doesn't exist
+        Err(Error::NotFound)
     }
 }
 
 #[update()]
-fn quote_update(original_post_id: u64, quote_content: String) -> u64 {
-    let caller = ic_cdk::caller();
-    let original_update = UPDATES.with(|updates| updates.borrow().get(&original_post_id).clone());
+fn quote_update(original_post_id: u64, quote_content: String) -> Result<u64, Error> {
+    let caller = ensure_caller_can_act()?;
+    check_rate_limit(caller, WriteAction::QuoteUpdate)?;
+    let original_update = UPDATES.with(|updates| updates.borrow().get(&original_post_id).clone())
+        .filter(|original| Viewer::current().can_see(original));
     
     if let Some(original) = original_update {
+        ensure_shareable(&original)?;
+        
+        // Quotes go through the same moderation as regular posts
+        let fingerprint = fingerprint(caller, &quote_content, time());
+        let verdict = strictest(screen_content(&quote_content)?, check_duplicates(&fingerprint)?);
+        let hidden_by = verdict.clone().filter(|v| v.action == RuleAction::Hide);
+        
         let id = NEXT_UPDATE_ID.with(|next_id| {
             let id = *next_id.borrow().get();
             next_id.borrow_mut().set(id + 1).unwrap();
@@ -558,134 +3297,309 @@ fn quote_update(original_post_id: u64, quote_content: String) -> u64 {
             quotes: 0,
             original_post_id: Some(original_post_id),
             quote_content: Some(original_content),
-            is_moderated: false,
-            moderation_reason: None,
-            is_hidden: false,
+            is_moderated: hidden_by.is_some(),
+            moderation_reason: hidden_by.as_ref().map(|v| v.reason.clone()),
+            is_hidden: hidden_by.is_some(),
+            moderation_rule_id: hidden_by.as_ref().and_then(|v| v.rule_id),
+            content_action: hidden_by.as_ref().map(|_| ContentAction::Hide),
+            author_labels: original.author_labels.clone(),
+            moderator_labels: original.moderator_labels.clone(),
+            deleted_at: None,
         };
         
         UPDATES.with(|updates| updates.borrow_mut().insert(id, quote));
+        FINGERPRINTS.with(|fingerprints| fingerprints.borrow_mut().insert(id, fingerprint));
+        record_verdict(caller, id, verdict);
         
         // Update quote count on original post
         let mut original_mut = original.clone();
         original_mut.quotes += 1;
         UPDATES.with(|updates| updates.borrow_mut().insert(original_post_id, original_mut));
         
-        id
+        Ok(id)
     } else {
-        0 // Return 0 if original post doesn't exist
+        Err(Error::NotFound)
     }
 }
 
 #[update()]
-fn flag_update(update_id: u64, reason: String, severity: ModerationSeverity) -> bool {
-    let caller = ic_cdk::caller();
+fn flag_update(update_id: u64, reason: String, severity: ModerationSeverity) -> Result<u64, Error> {
+    let caller = ensure_caller_can_act()?;
+    check_rate_limit(caller, WriteAction::FlagUpdate)?;
     let update = UPDATES.with(|updates| updates.borrow().get(&update_id).clone());
     
     if let Some(update_data) = update {
-        if update_data.is_moderated {
-            return false; // Cannot flag a moderated update
+        // Lighter actions leave the update up, so it can still be reported
+        if update_data.effective_action().is_some_and(|action| action >= ContentAction::Hide) {
+            return Err(Error::InvalidArgument("Update is already moderated".to_string()));
         }
         
-        // Generate a unique flag ID using timestamp
-        let flag_id = time();
-        let severity_clone = severity.clone();
+        let already_reported = REPORT_SUMMARIES.with(|summaries| summaries.borrow().get(&update_id))
+            .is_some_and(|summary| summary.reports.iter().any(|entry| entry.reporter == caller));
+        if already_reported {
+            return Err(Error::AlreadyExists);
+        }
         
-        MODERATION_FLAGS.with(|flags| {
-            flags.borrow_mut().insert(flag_id, ModerationFlag {
-                update_id,
-                flagged_by: caller,
-                reason: reason.clone(),
-                severity,
-                timestamp: time(),
-                is_resolved: false,
-            });
-        });
+        let mut stats = reporter_stats(caller);
+        let now = time();
+        if stats.is_throttled()
+            && stats.last_report_at.is_some_and(|last| now < last + THROTTLED_REPORT_INTERVAL_NS)
+        {
+            let frees_at = stats.last_report_at.unwrap_or(now) + THROTTLED_REPORT_INTERVAL_NS;
+            return Err(Error::RateLimited { retry_after_secs: (frees_at - now).div_ceil(1_000_000_000) });
+        }
+        stats.last_report_at = Some(now);
+        let weight = stats.report_weight(&severity);
+        REPORTER_STATS.with(|all| all.borrow_mut().insert(caller, stats));
         
-        // Add to user's warnings
-        USER_WARNINGS.with(|warnings| {
-            let mut user_warnings = warnings.borrow_mut();
-            let mut current = user_warnings.get(&update_data.author).unwrap_or(StableVec(vec![])).0;
-            current.push(Warning {
-                id: flag_id,
-                reason,
-                severity: severity_clone,
-                timestamp: time(),
-                expires_at: Some(time() + 86400_000_000_000), // 24 hours
-            });
-            user_warnings.insert(update_data.author, StableVec(current));
+        // The author is only warned once a moderator confirms the report
+        let flag_id = insert_flag(ModerationFlag {
+            update_id,
+            flagged_by: caller,
+            reason,
+            severity,
+            timestamp: now,
+            resolution: None,
         });
         
-        return true;
+        record_report(update_id, ReportEntry { reporter: caller, flag_id, weight });
+        
+        return Ok(flag_id);
     }
-    false
+    Err(Error::NotFound)
 }
 
-#[update()]
-fn resolve_flag(flag_id: u64) -> bool {
-    MODERATION_FLAGS.with(|flags| {
-        let mut flags_map = flags.borrow_mut();
-        if let Some(flag) = flags_map.get(&flag_id).clone() {
-            let mut resolved_flag = flag;
-            resolved_flag.is_resolved = true;
-            flags_map.insert(flag_id, resolved_flag);
-            return true;
+#[update(guard = "is_moderator")]
+fn resolve_flag(flag_id: u64, outcome: FlagOutcome, note: Option<String>) -> Result<(), Error> {
+    let caller = ic_cdk::caller();
+    let mut flag = MODERATION_FLAGS.with(|flags| flags.borrow().get(&flag_id)).ok_or(Error::NotFound)?;
+    
+    if !flag.is_open() {
+        return Err(Error::AlreadyExists);
+    }
+    let escalated = flag.resolution.is_some();
+    if escalated && (outcome == FlagOutcome::Escalated || is_admin().is_err()) {
+        return Err(Error::NotAuthorized);
+    }
+    // Items locked by another moderator are theirs to resolve until the claim lapses
+    if active_claim(flag.update_id).is_some_and(|claim| claim.moderator != caller) {
+        return Err(Error::NotAuthorized);
+    }
+    
+    let update_id = flag.update_id;
+    let author = UPDATES.with(|updates| updates.borrow().get(&update_id)).map(|u| u.author);
+    let before = flag.resolution.as_ref().map(|r| format!("{:?}", r.outcome));
+    match outcome {
+        FlagOutcome::Dismissed => {
+            // Older flags warned the author as soon as they were filed
+            if let Some(author) = author {
+                if let Some(warning_id) = flag_warning_id(author, flag_id, update_id) {
+                    retract_warning(author, warning_id);
+                }
+            }
         }
-        false
+        FlagOutcome::ContentRemoved | FlagOutcome::AuthorWarned => {
+            if outcome == FlagOutcome::ContentRemoved {
+                apply_moderation(update_id, note.clone().unwrap_or_else(|| flag.reason.clone()), ContentAction::Remove);
+            }
+            let already_warned = author.is_some_and(|author| flag_warning_id(author, flag_id, update_id).is_some());
+            if let Some(author) = author.filter(|_| !already_warned) {
+                add_warning(author, Warning {
+                    id: next_warning_id(),
+                    reason: flag.reason.clone(),
+                    severity: flag.severity.clone(),
+                    timestamp: time(),
+                    expires_at: Some(time() + 86_400_000_000_000), // 24 hours
+                    update_id: Some(update_id),
+                    flag_id: Some(flag_id),
+                });
+            }
+        }
+        FlagOutcome::Escalated => {}
+    }
+    record_review(flag.flagged_by, outcome);
+    
+    let severity = flag.severity.clone();
+    flag.resolution = Some(FlagResolution { outcome, moderator: caller, note: note.clone(), resolved_at: time() });
+    MODERATION_FLAGS.with(|flags| flags.borrow_mut().insert(flag_id, flag));
+    if outcome != FlagOutcome::Escalated {
+        remove_report(update_id, flag_id);
+    }
+    if !has_open_flags(update_id) {
+        QUEUE_CLAIMS.with(|claims| claims.borrow_mut().remove(&update_id));
+    }
+    record_audit(
+        caller, AuditAction::ResolveFlag, AuditTarget::Flag(flag_id),
+        note, Some(severity), before, Some(format!("{:?}", outcome)),
+    );
+    if outcome == FlagOutcome::Dismissed && !has_open_flags(update_id) {
+        lift_auto_hide(update_id, caller);
+    }
+    Ok(())
+}
+
+#[query(guard = "is_moderator")]
+fn get_reporter_reputation(reporter: Principal) -> ReporterReputation {
+    let stats = reporter_stats(reporter);
+    ReporterReputation {
+        accuracy: stats.accuracy(),
+        throttled: stats.is_throttled(),
+        ignored: stats.is_ignored(),
+        stats,
+    }
+}
+
+#[query]
+fn get_my_reports() -> Vec<ReportStatus> {
+    reports_filed_by(ic_cdk::caller())
+}
+
+fn reports_filed_by(reporter: Principal) -> Vec<ReportStatus> {
+    MODERATION_FLAGS.with(|flags| {
+        flags.borrow().iter()
+            .filter(|(_, flag)| flag.flagged_by == reporter)
+            .map(|(flag_id, flag)| {
+                // Escalation is internal; reporters just see the report as pending
+                let resolution = flag.resolution.filter(|r| r.outcome != FlagOutcome::Escalated);
+                ReportStatus {
+                    flag_id,
+                    update_id: flag.update_id,
+                    reason: flag.reason,
+                    severity: flag.severity,
+                    filed_at: flag.timestamp,
+                    outcome: resolution.as_ref().map(|r| r.outcome),
+                    resolved_at: resolution.map(|r| r.resolved_at),
+                }
+            })
+            .collect()
     })
 }
 
+#[query(guard = "is_moderator")]
+fn get_report_summary(update_id: u64) -> Option<ReportSummary> {
+    REPORT_SUMMARIES.with(|summaries| summaries.borrow().get(&update_id))
+}
+
+#[query(guard = "is_moderator")]
+fn get_report_thresholds() -> ReportThresholds {
+    REPORT_THRESHOLDS.with(|thresholds| thresholds.borrow().get().clone())
+}
+
+#[update(guard = "is_admin")]
+fn set_report_thresholds(thresholds: ReportThresholds) -> Result<(), Error> {
+    if thresholds.min_reporters == 0 || thresholds.min_score <= 0.0 {
+        return Err(Error::InvalidArgument("Thresholds must be positive".to_string()));
+    }
+    let after = format!("{:?}", thresholds);
+    let previous = REPORT_THRESHOLDS.with(|current| current.borrow_mut().set(thresholds))
+        .map_err(|_| Error::InvalidArgument("Thresholds could not be stored".to_string()))?;
+    record_audit(
+        ic_cdk::caller(), AuditAction::UpdateSettings, AuditTarget::Setting("report_thresholds".to_string()),
+        None, None, Some(format!("{:?}", previous)), Some(after),
+    );
+    Ok(())
+}
+
+#[update(guard = "is_moderator")]
+fn moderate_update(update_id: u64, reason: String, severity: ModerationSeverity) -> bool {
+    let action = ContentAction::for_severity(&severity);
+    moderate_with_action(update_id, reason, Some(severity), action)
+}
+
+// Lets a moderator pick a rung other than the one the severity maps to
+#[update(guard = "is_moderator")]
+fn set_content_action(update_id: u64, action: ContentAction, reason: String) -> bool {
+    moderate_with_action(update_id, reason, None, action)
+}
+
+fn moderate_with_action(update_id: u64, reason: String, severity: Option<ModerationSeverity>, action: ContentAction) -> bool {
+    let before = update_snapshot(update_id);
+    if !apply_moderation(update_id, reason.clone(), action) {
+        return false;
+    }
+    record_audit(
+        ic_cdk::caller(), AuditAction::ModerateUpdate, AuditTarget::Update(update_id),
+        Some(reason), severity, before, update_snapshot(update_id),
+    );
+    true
+}
+
+#[update(guard = "is_moderator")]
+fn unmoderate_update(update_id: u64) -> bool {
+    let before = update_snapshot(update_id);
+    if !clear_moderation(update_id) {
+        return false;
+    }
+    record_audit(
+        ic_cdk::caller(), AuditAction::UnmoderateUpdate, AuditTarget::Update(update_id),
+        None, None, before, update_snapshot(update_id),
+    );
+    true
+}
+
 #[update()]
-fn moderate_update(update_id: u64, reason: String, _severity: ModerationSeverity) -> bool {
-    let update = UPDATES.with(|updates| updates.borrow().get(&update_id).clone());
-    
-    if let Some(mut update_data) = update {
-        update_data.is_moderated = true;
-        update_data.moderation_reason = Some(reason);
-        update_data.is_hidden = true; // Hide the update
-        UPDATES.with(|updates| updates.borrow_mut().insert(update_id, update_data));
-        return true;
+fn follow(user: Principal) -> Result<FollowState, Error> {
+    let caller = ensure_caller_can_act()?;
+    check_rate_limit(caller, WriteAction::Follow)?;
+    if caller != user && is_private_account(user) && !is_following(caller, user) {
+        FOLLOW_REQUESTS.with(|requests| {
+            let mut requests_map = requests.borrow_mut();
+            let mut pending = requests_map.get(&user).unwrap_or(StableVec(vec![])).0;
+            if !pending.iter().any(|request| request.requester == caller) {
+                pending.push(FollowRequest { requester: caller, requested_at: time() });
+            }
+            requests_map.insert(user, StableVec(pending));
+        });
+        return Ok(FollowState::Requested);
     }
-    false
+    add_follow(caller, user);
+    Ok(FollowState::Following)
 }
 
+// Private accounts. Suspended users can still lock their account down
 #[update()]
-fn unmoderate_update(update_id: u64) -> bool {
-    let update = UPDATES.with(|updates| updates.borrow().get(&update_id).clone());
+fn set_account_privacy(is_private: bool) -> Result<(), Error> {
+    let caller = ensure_registered()?;
+    let mut user = USERS.with(|users| users.borrow().get(&caller)).ok_or(Error::NotFound)?;
+    user.is_private = Some(is_private);
+    USERS.with(|users| users.borrow_mut().insert(caller, user));
     
-    if let Some(mut update_data) = update {
-        update_data.is_moderated = false;
-        update_data.moderation_reason = None;
-        update_data.is_hidden = false; // Unhide the update
-        UPDATES.with(|updates| updates.borrow_mut().insert(update_id, update_data));
-        return true;
+    // Going public lets everyone who asked in
+    if !is_private {
+        let pending = FOLLOW_REQUESTS.with(|requests| requests.borrow_mut().remove(&caller));
+        for request in pending.map(|pending| pending.0).unwrap_or_default() {
+            add_follow(request.requester, caller);
+        }
     }
-    false
+    Ok(())
+}
+
+#[query]
+fn get_follow_requests() -> Vec<FollowRequest> {
+    FOLLOW_REQUESTS.with(|requests| requests.borrow().get(&ic_cdk::caller()).unwrap_or(StableVec(vec![])).0)
 }
 
 #[update()]
-fn follow(user: Principal) {
-    let caller = ic_cdk::caller();
-    FOLLOWS.with(|follows| {
-        let mut follows_map = follows.borrow_mut();
-        let mut current = follows_map.get(&caller).unwrap_or(StableVec(vec![])).0;
-        if !current.contains(&user) {
-            current.push(user);
-        }
-        follows_map.insert(caller, StableVec(current));
-    });
-    FOLLOWERS.with(|followers| {
-        let mut followers_map = followers.borrow_mut();
-        let mut current = followers_map.get(&user).unwrap_or(StableVec(vec![])).0;
-        if !current.contains(&caller) {
-            current.push(caller);
-        }
-        followers_map.insert(user, StableVec(current));
-    });
+fn approve_follow_request(requester: Principal) -> Result<(), Error> {
+    let caller = ensure_registered()?;
+    take_follow_request(caller, requester).ok_or(Error::NotFound)?;
+    add_follow(requester, caller);
+    Ok(())
 }
 
 #[update()]
-fn like_update(update_id: u64) -> bool {
-    let caller = ic_cdk::caller();
+fn deny_follow_request(requester: Principal) -> Result<(), Error> {
+    let caller = ensure_registered()?;
+    take_follow_request(caller, requester).map(|_| ()).ok_or(Error::NotFound)
+}
+
+#[update()]
+fn like_update(update_id: u64) -> Result<bool, Error> {
+    let caller = ensure_caller_can_act()?;
+    check_rate_limit(caller, WriteAction::LikeUpdate)?;
+    if !Viewer::current().can_see_id(update_id) {
+        return Err(Error::NotFound);
+    }
     let mut liked = false;
     
     LIKES.with(|likes| {
@@ -718,11 +3632,14 @@ fn like_update(update_id: u64) -> bool {
         }
     });
     
-    liked
+    Ok(liked)
 }
 
 #[query]
 fn has_liked_update(update_id: u64, user: Principal) -> bool {
+    if !Viewer::current().can_see_id(update_id) {
+        return false;
+    }
     LIKES.with(|likes| {
         likes.borrow().get(&update_id).unwrap_or(StableVec(vec![])).0.contains(&user)
     })
@@ -730,19 +3647,23 @@ fn has_liked_update(update_id: u64, user: Principal) -> bool {
 
 #[query]
 fn has_reposted_update(update_id: u64, user: Principal) -> bool {
+    if !Viewer::current().can_see_id(update_id) {
+        return false;
+    }
     REPOSTS.with(|reposts| {
         reposts.borrow().get(&update_id).unwrap_or(StableVec(vec![])).0.contains(&user)
     })
 }
 
 #[query()]
-fn get_timeline(page: u64, page_size: u64) -> Vec<Update> {
-    let caller = ic_cdk::caller();
+fn get_timeline(page: u64, page_size: u64) -> Vec<FeedItem> {
+    let viewer = Viewer::current();
+    let caller = viewer.principal;
     let followed = FOLLOWS.with(|follows| follows.borrow().get(&caller).unwrap_or(StableVec(vec![])).0);
-    let mut timeline: Vec<Update> = UPDATES.with(|updates| {
-        updates.borrow().iter().filter_map(|(_, update)| if followed.contains(&update.author) || update.author == caller { Some(update.clone()) } else { None }).collect()
+    let mut timeline: Vec<FeedItem> = UPDATES.with(|updates| {
+        updates.borrow().iter().filter_map(|(_, update)| if followed.contains(&update.author) || update.author == caller { viewer.view(update) } else { None }).collect()
     });
-    timeline.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    timeline.sort_by_key(|item| std::cmp::Reverse(item.update.ranking_timestamp()));
     let start = (page * page_size) as usize;
     
     // Check if start index is beyond the vector length
@@ -755,16 +3676,17 @@ fn get_timeline(page: u64, page_size: u64) -> Vec<Update> {
 }
 
 #[query]
-fn search_updates(keyword: String) -> Vec<Update> {
+fn search_updates(keyword: String) -> Vec<FeedItem> {
+    let viewer = Viewer::current();
     UPDATES.with(|updates| {
-        updates.borrow().iter().filter_map(|(_, update)| if update.content.to_lowercase().contains(&keyword.to_lowercase()) { Some(update.clone()) } else { None }).collect()
+        updates.borrow().iter().filter_map(|(_, update)| if update.content.to_lowercase().contains(&keyword.to_lowercase()) && update.is_discoverable() { viewer.view(update) } else { None }).collect()
     })
 }
 
 #[query]
 fn search_users(handle_prefix: String) -> Vec<(Principal, User)> {
     USERS.with(|users| {
-        users.borrow().iter().filter_map(|(p, u)| if u.handle.to_lowercase().starts_with(&handle_prefix.to_lowercase()) { Some((p.clone(), u.clone())) } else { None }).collect()
+        users.borrow().iter().filter_map(|(p, u)| if u.handle.to_lowercase().starts_with(&handle_prefix.to_lowercase()) { Some((p, u.clone())) } else { None }).collect()
     })
 }
 
@@ -779,36 +3701,100 @@ fn get_following(principal: Principal) -> Vec<Principal> {
 }
 
 #[query]
-fn get_user_updates(principal: Principal, page: u64, page_size: u64) -> Vec<Update> {
-    let mut user_updates: Vec<Update> = UPDATES.with(|updates| {
-        updates.borrow().iter().filter_map(|(_, update)| if update.author == principal { Some(update.clone()) } else { None }).collect()
+fn get_user_updates(principal: Principal, page: u64, page_size: u64) -> Vec<FeedItem> {
+    let viewer = Viewer::current();
+    let mut user_updates: Vec<FeedItem> = UPDATES.with(|updates| {
+        updates.borrow().iter().filter_map(|(_, update)| if update.author == principal { viewer.view(update) } else { None }).collect()
     });
-    user_updates.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    user_updates.sort_by_key(|item| std::cmp::Reverse(item.update.timestamp));
     let start = (page * page_size) as usize;
     
     // Check if start index is beyond the vector length
     if start >= user_updates.len() {
         return vec![];
     }
-    
-    let end = std::cmp::min(start + page_size as usize, user_updates.len());
-    user_updates[start..end].to_vec()
+    
+    let end = std::cmp::min(start + page_size as usize, user_updates.len());
+    user_updates[start..end].to_vec()
+}
+
+// Content labels
+#[update()]
+fn set_update_labels(update_id: u64, labels: Vec<ContentLabel>) -> Result<(), Error> {
+    let caller = ensure_caller_can_act()?;
+    let mut update = UPDATES.with(|updates| updates.borrow().get(&update_id)).ok_or(Error::NotFound)?;
+    if update.author != caller {
+        return Err(Error::NotAuthorized);
+    }
+    update.author_labels = Some(validate_labels(labels)?);
+    UPDATES.with(|updates| updates.borrow_mut().insert(update_id, update));
+    Ok(())
+}
+
+#[update(guard = "is_moderator")]
+fn set_moderator_labels(update_id: u64, labels: Vec<ContentLabel>) -> Result<(), Error> {
+    let labels = validate_labels(labels)?;
+    let mut update = UPDATES.with(|updates| updates.borrow().get(&update_id)).ok_or(Error::NotFound)?;
+    let before = format!("{:?}", update.moderator_labels.clone().unwrap_or_default());
+    let after = format!("{:?}", labels);
+    update.moderator_labels = Some(labels);
+    UPDATES.with(|updates| updates.borrow_mut().insert(update_id, update));
+    record_audit(
+        ic_cdk::caller(), AuditAction::LabelUpdate, AuditTarget::Update(update_id),
+        None, None, Some(before), Some(after),
+    );
+    Ok(())
+}
+
+#[query]
+fn get_content_preferences() -> Vec<(ContentLabel, LabelPreference)> {
+    viewer_preferences(ic_cdk::caller())
+}
+
+#[update()]
+fn set_content_preferences(preferences: Vec<(ContentLabel, LabelPreference)>) -> Result<(), Error> {
+    let caller = ensure_caller_can_act()?;
+    let mut user = USERS.with(|users| users.borrow().get(&caller)).ok_or(Error::NotFound)?;
+    let mut merged = viewer_preferences(caller);
+    for (label, preference) in preferences {
+        if let Some(entry) = merged.iter_mut().find(|(l, _)| *l == label) {
+            entry.1 = preference;
+        }
+    }
+    user.content_preferences = Some(merged);
+    USERS.with(|users| users.borrow_mut().insert(caller, user));
+    Ok(())
 }
 
 // New moderation query functions
 #[query]
 fn get_user_warnings(principal: Principal) -> Vec<Warning> {
+    // Expired warnings may not have been swept into history yet
+    let now = time();
     USER_WARNINGS.with(|warnings| {
         warnings.borrow().get(&principal).unwrap_or(StableVec(vec![])).0
     })
+    .into_iter()
+    .filter(|w| w.is_active(now))
+    .collect()
 }
 
 #[query]
+fn get_warning_history(principal: Principal) -> Result<Vec<HistoricalWarning>, Error> {
+    // Users may see their own history, moderators anyone's
+    if ic_cdk::caller() != principal && is_moderator().is_err() {
+        return Err(Error::NotAuthorized);
+    }
+    
+    Ok(warning_history(principal))
+}
+
+#[query(guard = "is_moderator")]
 fn get_flagged_content() -> Vec<(u64, ModerationFlag)> {
     MODERATION_FLAGS.with(|flags| {
         flags.borrow().iter()
             .filter_map(|(id, flag)| {
-                if !flag.is_resolved {
+                if flag.is_open() {
                     Some((id, flag.clone()))
                 } else {
                     None
@@ -818,7 +3804,113 @@ fn get_flagged_content() -> Vec<(u64, ModerationFlag)> {
     })
 }
 
-#[query]
+// Moderation queue
+#[query(guard = "is_moderator")]
+fn get_moderation_queue(page: u64, page_size: u64, include_claimed: bool) -> QueuePage {
+    let caller = ic_cdk::caller();
+    let page_size = page_size.clamp(1, MAX_QUEUE_PAGE_SIZE);
+    
+    let mut grouped: HashMap<u64, Vec<(u64, ModerationFlag)>> = HashMap::new();
+    MODERATION_FLAGS.with(|flags| {
+        for (id, flag) in flags.borrow().iter().filter(|(_, flag)| flag.is_open()) {
+            grouped.entry(flag.update_id).or_default().push((id, flag));
+        }
+    });
+    
+    // Items claimed by someone else are skipped unless asked for
+    let mut queue: Vec<_> = grouped.into_iter()
+        .map(|(update_id, flags)| (update_id, flags, active_claim(update_id)))
+        .filter(|(_, _, claim)| include_claimed || claim.as_ref().is_none_or(|c| c.moderator == caller))
+        .collect();
+    
+    // Most severe first, then oldest report first
+    let key = |flags: &Vec<(u64, ModerationFlag)>| {
+        let max_severity = flags.iter().map(|(_, f)| f.severity.clone()).max().unwrap_or(ModerationSeverity::Low);
+        let oldest = flags.iter().map(|(_, f)| f.timestamp).min().unwrap_or(0);
+        (max_severity, oldest)
+    };
+    queue.sort_by(|(a_id, a, _), (b_id, b, _)| {
+        let (a_severity, a_oldest) = key(a);
+        let (b_severity, b_oldest) = key(b);
+        b_severity.cmp(&a_severity).then(a_oldest.cmp(&b_oldest)).then(a_id.cmp(b_id))
+    });
+    
+    let total = queue.len() as u64;
+    // Pages past the end come back empty
+    let offset = page.saturating_mul(page_size).min(total);
+    let items = queue.into_iter()
+        .skip(offset as usize)
+        .take(page_size as usize)
+        .map(|(update_id, flags, claim)| {
+            let (max_severity, oldest_flag_at) = key(&flags);
+            let update = UPDATES.with(|updates| updates.borrow().get(&update_id));
+            let author_id = update.as_ref().map(|u| u.author);
+            let now = time();
+            QueueItem {
+                update_id,
+                author: author_id.and_then(|a| USERS.with(|users| users.borrow().get(&a))),
+                author_warnings: author_id
+                    .map(|a| USER_WARNINGS.with(|w| w.borrow().get(&a).unwrap_or(StableVec(vec![])).0))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|w| w.is_active(now))
+                    .collect(),
+                author_warning_history: author_id.map(warning_history).unwrap_or_default(),
+                report_score: REPORT_SUMMARIES.with(|s| s.borrow().get(&update_id)).map_or(0.0, |s| s.score),
+                update,
+                flags,
+                max_severity,
+                oldest_flag_at,
+                claim,
+            }
+        })
+        .collect();
+    
+    QueuePage { items, total }
+}
+
+#[update(guard = "is_moderator")]
+fn claim_queue_item(update_id: u64) -> Result<QueueClaim, Error> {
+    let caller = ic_cdk::caller();
+    if !has_open_flags(update_id) {
+        return Err(Error::NotFound);
+    }
+    // Claiming again renews the caller's own lock
+    if active_claim(update_id).is_some_and(|claim| claim.moderator != caller) {
+        return Err(Error::AlreadyExists);
+    }
+    Ok(store_claim(update_id, caller, None))
+}
+
+#[update(guard = "is_moderator")]
+fn release_queue_item(update_id: u64) -> Result<(), Error> {
+    let claim = active_claim(update_id).ok_or(Error::NotFound)?;
+    if claim.moderator != ic_cdk::caller() && is_admin().is_err() {
+        return Err(Error::NotAuthorized);
+    }
+    QUEUE_CLAIMS.with(|claims| claims.borrow_mut().remove(&update_id));
+    Ok(())
+}
+
+#[update(guard = "is_admin")]
+fn assign_queue_item(update_id: u64, moderator: Principal) -> Result<QueueClaim, Error> {
+    if !has_open_flags(update_id) {
+        return Err(Error::NotFound);
+    }
+    if role_of(&moderator).rank() < Role::Moderator.rank() {
+        return Err(Error::InvalidArgument("Assignee is not a moderator".to_string()));
+    }
+    let before = active_claim(update_id).map(|claim| claim.moderator.to_text());
+    let caller = ic_cdk::caller();
+    let claim = store_claim(update_id, moderator, Some(caller));
+    record_audit(
+        caller, AuditAction::AssignQueueItem, AuditTarget::Update(update_id),
+        None, None, before, Some(moderator.to_text()),
+    );
+    Ok(claim)
+}
+
+#[query(guard = "is_moderator")]
 fn get_moderated_updates() -> Vec<Update> {
     UPDATES.with(|updates| {
         updates.borrow().iter()
@@ -833,57 +3925,726 @@ fn get_moderated_updates() -> Vec<Update> {
     })
 }
 
-#[update()]
+#[update(guard = "is_moderator")]
 fn suspend_user(principal: Principal, duration_hours: u64) -> bool {
-    let user = USERS.with(|users| users.borrow().get(&principal).clone());
-    
-    if let Some(mut user_data) = user {
-        user_data.is_suspended = true;
-        user_data.suspension_until = Some(time() + (duration_hours * 3600_000_000_000)); // Convert hours to nanoseconds
-        USERS.with(|users| users.borrow_mut().insert(principal, user_data));
-        true
-    } else {
-        false
+    let moderator = ic_cdk::caller();
+    let reason = "Suspended by moderator".to_string();
+    let before = user_snapshot(principal);
+    if !apply_suspension(principal, duration_hours, reason.clone(), SuspensionSource::Moderator(moderator)) {
+        return false;
+    }
+    record_audit(
+        moderator, AuditAction::SuspendUser, AuditTarget::User(principal),
+        Some(reason), None, before, user_snapshot(principal),
+    );
+    true
+}
+
+#[query]
+fn get_suspension_history(principal: Principal) -> Result<Vec<SuspensionRecord>, Error> {
+    // Users may see their own history, moderators anyone's
+    if ic_cdk::caller() != principal && is_moderator().is_err() {
+        return Err(Error::NotAuthorized);
     }
+    Ok(SUSPENSIONS.with(|suspensions| suspensions.borrow().get(&principal).unwrap_or(StableVec(vec![])).0))
 }
 
-#[update()]
+#[query(guard = "is_moderator")]
+fn get_rate_limit_policy() -> RateLimitPolicy {
+    RATE_LIMIT_POLICY.with(|policy| policy.borrow().get().clone())
+}
+
+#[update(guard = "is_admin")]
+fn set_rate_limit_policy(policy: RateLimitPolicy) -> Result<(), Error> {
+    for rule in &policy.rules {
+        if rule.window_secs == 0 || rule.restricted_max_requests == 0 || rule.restricted_max_requests > rule.max_requests {
+            return Err(Error::InvalidArgument(
+                "Windows must be positive and restricted limits between 1 and the regular limit".to_string(),
+            ));
+        }
+    }
+    let after = format!("{:?}", policy);
+    let previous = RATE_LIMIT_POLICY.with(|current| current.borrow_mut().set(policy))
+        .map_err(|_| Error::InvalidArgument("Policy could not be stored".to_string()))?;
+    record_audit(
+        ic_cdk::caller(), AuditAction::UpdateSettings, AuditTarget::Setting("rate_limit_policy".to_string()),
+        None, None, Some(format!("{:?}", previous)), Some(after),
+    );
+    Ok(())
+}
+
+#[query(guard = "is_moderator")]
+fn get_spam_policy() -> SpamPolicy {
+    SPAM_POLICY.with(|policy| policy.borrow().get().clone())
+}
+
+#[update(guard = "is_admin")]
+fn set_spam_policy(policy: SpamPolicy) -> Result<(), Error> {
+    if policy.window_hours == 0 || policy.max_self_duplicates == 0 || policy.cross_account_threshold == 0 {
+        return Err(Error::InvalidArgument("Window, duplicate limit and account threshold must be positive".to_string()));
+    }
+    if policy.max_hamming_distance > 16 {
+        return Err(Error::InvalidArgument("Hamming distance may be at most 16 bits".to_string()));
+    }
+    let after = format!("{:?}", policy);
+    let previous = SPAM_POLICY.with(|current| current.borrow_mut().set(policy))
+        .map_err(|_| Error::InvalidArgument("Policy could not be stored".to_string()))?;
+    record_audit(
+        ic_cdk::caller(), AuditAction::UpdateSettings, AuditTarget::Setting("spam_policy".to_string()),
+        None, None, Some(format!("{:?}", previous)), Some(after),
+    );
+    Ok(())
+}
+
+#[query(guard = "is_moderator")]
+fn get_enforcement_policy() -> EnforcementPolicy {
+    ENFORCEMENT_POLICY.with(|policy| policy.borrow().get().clone())
+}
+
+#[update(guard = "is_admin")]
+fn set_enforcement_policy(policy: EnforcementPolicy) -> Result<(), Error> {
+    for rule in &policy.rules {
+        if rule.strike_count == 0 || rule.window_hours == 0 || rule.suspension_hours == 0 {
+            return Err(Error::InvalidArgument("Strike count, window and suspension length must be positive".to_string()));
+        }
+    }
+    let after = format!("{:?}", policy);
+    let previous = ENFORCEMENT_POLICY.with(|current| current.borrow_mut().set(policy))
+        .map_err(|_| Error::InvalidArgument("Policy could not be stored".to_string()))?;
+    record_audit(
+        ic_cdk::caller(), AuditAction::UpdateSettings, AuditTarget::Setting("enforcement_policy".to_string()),
+        None, None, Some(format!("{:?}", previous)), Some(after),
+    );
+    Ok(())
+}
+
+#[update(guard = "is_moderator")]
 fn unsuspend_user(principal: Principal) -> bool {
-    let user = USERS.with(|users| users.borrow().get(&principal).clone());
-    
-    if let Some(mut user_data) = user {
-        user_data.is_suspended = false;
-        user_data.suspension_until = None;
-        USERS.with(|users| users.borrow_mut().insert(principal, user_data));
-        true
-    } else {
-        false
+    let before = user_snapshot(principal);
+    if !lift_suspension(principal) {
+        return false;
     }
+    record_audit(
+        ic_cdk::caller(), AuditAction::UnsuspendUser, AuditTarget::User(principal),
+        None, None, before, user_snapshot(principal),
+    );
+    true
 }
 
-#[update()]
+#[update(guard = "is_admin")]
 fn verify_user(principal: Principal) -> bool {
     let user = USERS.with(|users| users.borrow().get(&principal).clone());
     
     if let Some(mut user_data) = user {
+        let before = user_snapshot(principal);
         user_data.is_verified = true;
         USERS.with(|users| users.borrow_mut().insert(principal, user_data));
+        record_audit(
+            ic_cdk::caller(), AuditAction::VerifyUser, AuditTarget::User(principal),
+            None, None, before, user_snapshot(principal),
+        );
         true
     } else {
         false
     }
 }
 
+// Appeals
+#[update()]
+fn file_appeal(target: AppealTarget, statement: String) -> Result<u64, Error> {
+    // Suspended users can still appeal
+    let caller = ensure_registered()?;
+    
+    if statement.trim().is_empty() || statement.chars().count() > 1000 {
+        return Err(Error::InvalidArgument("Statement must be between 1 and 1000 characters".to_string()));
+    }
+    
+    match target {
+        AppealTarget::Update(update_id) => {
+            let update = UPDATES.with(|updates| updates.borrow().get(&update_id)).ok_or(Error::NotFound)?;
+            if update.author != caller {
+                return Err(Error::NotAuthorized);
+            }
+            if !update.is_moderated && !update.is_hidden {
+                return Err(Error::InvalidArgument("Update is not moderated".to_string()));
+            }
+        }
+        AppealTarget::Warning(warning_id) => {
+            // Expired warnings still count as strikes, so they can be appealed too
+            let has_warning = get_user_warnings(caller).iter().any(|w| w.id == warning_id)
+                || warning_history(caller).iter()
+                    .any(|h| h.outcome == WarningOutcome::Expired && h.warning.id == warning_id);
+            if !has_warning {
+                return Err(Error::NotFound);
+            }
+        }
+    }
+    
+    // Only one open appeal per target
+    let already_pending = APPEALS.with(|appeals| {
+        appeals.borrow().iter().any(|(_, appeal)| {
+            appeal.appellant == caller && appeal.target == target && appeal.status == AppealStatus::Pending
+        })
+    });
+    if already_pending {
+        return Err(Error::AlreadyExists);
+    }
+    
+    let id = NEXT_APPEAL_ID.with(|next_id| {
+        let id = *next_id.borrow().get();
+        next_id.borrow_mut().set(id + 1).unwrap();
+        id
+    });
+    
+    APPEALS.with(|appeals| appeals.borrow_mut().insert(id, Appeal {
+        id,
+        appellant: caller,
+        target,
+        statement,
+        status: AppealStatus::Pending,
+        filed_at: time(),
+        reviewed_by: None,
+        reviewed_at: None,
+        review_note: None,
+    }));
+    
+    Ok(id)
+}
+
+#[query]
+fn get_my_appeals() -> Vec<Appeal> {
+    let caller = ic_cdk::caller();
+    APPEALS.with(|appeals| {
+        appeals.borrow().iter()
+            .filter_map(|(_, appeal)| if appeal.appellant == caller { Some(appeal) } else { None })
+            .collect()
+    })
+}
+
+#[query(guard = "is_moderator")]
+fn get_appeal_queue() -> Vec<Appeal> {
+    let mut queue: Vec<Appeal> = APPEALS.with(|appeals| {
+        appeals.borrow().iter()
+            .filter_map(|(_, appeal)| if appeal.status == AppealStatus::Pending { Some(appeal) } else { None })
+            .collect()
+    });
+    queue.sort_by_key(|appeal| appeal.filed_at);
+    queue
+}
+
+#[update(guard = "is_moderator")]
+fn decide_appeal(appeal_id: u64, decision: AppealDecision, note: Option<String>) -> Result<(), Error> {
+    let mut appeal = APPEALS.with(|appeals| appeals.borrow().get(&appeal_id)).ok_or(Error::NotFound)?;
+    if appeal.status != AppealStatus::Pending {
+        return Err(Error::InvalidArgument("Appeal has already been decided".to_string()));
+    }
+    
+    if decision == AppealDecision::Overturned {
+        match appeal.target {
+            AppealTarget::Update(update_id) => {
+                clear_moderation(update_id);
+                let expired = WARNING_HISTORY.with(|history| history.borrow().get(&appeal.appellant).unwrap_or(StableVec(vec![])).0)
+                    .into_iter()
+                    .filter(|entry| entry.outcome == WarningOutcome::Expired)
+                    .map(|entry| entry.warning);
+                let related: Vec<u64> = get_user_warnings(appeal.appellant).into_iter()
+                    .chain(expired)
+                    .filter(|w| w.update_id == Some(update_id))
+                    .map(|w| w.id)
+                    .collect();
+                for warning_id in related {
+                    retract_warning(appeal.appellant, warning_id);
+                }
+            }
+            AppealTarget::Warning(warning_id) => {
+                if let Some(warning) = retract_warning(appeal.appellant, warning_id) {
+                    if let Some(update_id) = warning.update_id {
+                        clear_moderation(update_id);
+                    }
+                }
+            }
+        }
+    }
+    
+    let before = format!("{:?}", appeal.status);
+    appeal.status = match decision {
+        AppealDecision::Upheld => AppealStatus::Upheld,
+        AppealDecision::Overturned => AppealStatus::Overturned,
+    };
+    appeal.reviewed_by = Some(ic_cdk::caller());
+    appeal.reviewed_at = Some(time());
+    appeal.review_note = note.clone();
+    let after = format!("{:?}", appeal.status);
+    APPEALS.with(|appeals| appeals.borrow_mut().insert(appeal_id, appeal));
+    record_audit(
+        ic_cdk::caller(), AuditAction::DecideAppeal, AuditTarget::Appeal(appeal_id),
+        note, None, Some(before), Some(after),
+    );
+    
+    Ok(())
+}
+
+// Moderation rules
+#[query(guard = "is_moderator")]
+fn list_moderation_rules() -> Vec<ModerationRule> {
+    MODERATION_RULES.with(|rules| rules.borrow().iter().map(|(_, rule)| rule).collect())
+}
+
+#[update(guard = "is_admin")]
+fn add_moderation_rule(input: ModerationRuleInput) -> Result<u64, Error> {
+    validate_rule(&input)?;
+    let rule_id = store_rule(None, input, ic_cdk::caller());
+    record_audit(
+        ic_cdk::caller(), AuditAction::CreateRule, AuditTarget::Rule(rule_id),
+        None, None, None, rule_snapshot(rule_id),
+    );
+    Ok(rule_id)
+}
+
+#[update(guard = "is_admin")]
+fn update_moderation_rule(rule_id: u64, input: ModerationRuleInput) -> Result<(), Error> {
+    let before = rule_snapshot(rule_id).ok_or(Error::NotFound)?;
+    validate_rule(&input)?;
+    store_rule(Some(rule_id), input, ic_cdk::caller());
+    record_audit(
+        ic_cdk::caller(), AuditAction::UpdateRule, AuditTarget::Rule(rule_id),
+        None, None, Some(before), rule_snapshot(rule_id),
+    );
+    Ok(())
+}
+
+#[update(guard = "is_admin")]
+fn delete_moderation_rule(rule_id: u64) -> Result<(), Error> {
+    let before = rule_snapshot(rule_id).ok_or(Error::NotFound)?;
+    MODERATION_RULES.with(|rules| rules.borrow_mut().remove(&rule_id));
+    record_audit(
+        ic_cdk::caller(), AuditAction::DeleteRule, AuditTarget::Rule(rule_id),
+        None, None, Some(before), None,
+    );
+    Ok(())
+}
+
+// Dry run of the moderation pipeline, for testing rule changes
+#[query(guard = "is_moderator")]
+fn preview_moderation(content: String) -> Option<ModerationVerdict> {
+    filter_content(&content)
+}
+
+// Role management
+#[update(guard = "is_owner")]
+fn grant_role(principal: Principal, role: Role) -> bool {
+    // Ownership only changes hands through transfer_ownership
+    if role == Role::Owner || role_of(&principal) == Role::Owner {
+        return false;
+    }
+
+    let before = format!("{:?}", role_of(&principal));
+    ROLES.with(|roles| {
+        let mut roles_map = roles.borrow_mut();
+        if role == Role::User {
+            roles_map.remove(&principal);
+        } else {
+            roles_map.insert(principal, role);
+        }
+    });
+    record_audit(
+        ic_cdk::caller(), AuditAction::GrantRole, AuditTarget::User(principal),
+        None, None, Some(before), Some(format!("{:?}", role)),
+    );
+    true
+}
+
+#[update(guard = "is_owner")]
+fn revoke_role(principal: Principal) -> bool {
+    let role = role_of(&principal);
+    if role == Role::Owner {
+        return false;
+    }
+    if ROLES.with(|roles| roles.borrow_mut().remove(&principal).is_none()) {
+        return false;
+    }
+    record_audit(
+        ic_cdk::caller(), AuditAction::RevokeRole, AuditTarget::User(principal),
+        None, None, Some(format!("{:?}", role)), Some(format!("{:?}", Role::User)),
+    );
+    true
+}
+
+// The previous owner stays on as an admin
+#[update(guard = "is_owner")]
+fn transfer_ownership(new_owner: Principal) -> Result<(), Error> {
+    let caller = ic_cdk::caller();
+    if new_owner == caller {
+        return Err(Error::AlreadyExists);
+    }
+    if !USERS.with(|users| users.borrow().contains_key(&new_owner)) {
+        return Err(Error::NotRegistered);
+    }
+    let before = format!("{:?}", role_of(&new_owner));
+    ROLES.with(|roles| {
+        let mut roles_map = roles.borrow_mut();
+        roles_map.insert(new_owner, Role::Owner);
+        roles_map.insert(caller, Role::Admin);
+    });
+    record_audit(
+        caller, AuditAction::TransferOwnership, AuditTarget::User(new_owner),
+        None, None, Some(before), Some(format!("{:?}", Role::Owner)),
+    );
+    Ok(())
+}
+
+#[query]
+fn get_role(principal: Principal) -> Role {
+    role_of(&principal)
+}
+
+#[query(guard = "is_admin")]
+fn list_roles() -> Vec<(Principal, Role)> {
+    ROLES.with(|roles| roles.borrow().iter().collect())
+}
+
+// Audit log
+#[query(guard = "is_admin")]
+fn get_audit_log(query: AuditQuery) -> AuditPage {
+    let limit = query.limit.unwrap_or(50).clamp(1, 200) as usize;
+    
+    AUDIT_LOG.with(|log| {
+        let log = log.borrow();
+        let mut entries = Vec::new();
+        let mut next_cursor = None;
+        let mut id = query.before_id.unwrap_or(log.len()).min(log.len());
+        
+        while id > 0 {
+            id -= 1;
+            let entry = match log.get(id) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            // Entries are appended in time order, so older ones can't match either
+            if query.since.is_some_and(|since| entry.timestamp < since) {
+                break;
+            }
+            let matches = query.actor.is_none_or(|actor| entry.actor == actor)
+                && query.action.is_none_or(|action| entry.action == action)
+                && query.target.as_ref().is_none_or(|target| &entry.target == target)
+                && query.until.is_none_or(|until| entry.timestamp <= until);
+            if !matches {
+                continue;
+            }
+            if entries.len() == limit {
+                next_cursor = Some(entry.id + 1);
+                break;
+            }
+            entries.push(entry);
+        }
+        
+        AuditPage { entries, next_cursor }
+    })
+}
+
 // New AI Insights endpoint
 #[query]
 fn get_ai_insights(content: String) -> AIInsights {
     analyze_content_ai(&content)
 }
 
+// Drops ingress calls that would be rejected anyway before they cost cycles
+#[inspect_message]
+fn inspect_message() {
+    let method = ic_cdk::api::call::method_name();
+    let caller = ic_cdk::caller();
+    let accepted = if REGISTERED_WRITE_METHODS.contains(&method.as_str()) {
+        caller != Principal::anonymous() && USERS.with(|users| users.borrow().contains_key(&caller))
+    } else if method == "register" {
+        caller != Principal::anonymous()
+    } else {
+        true
+    };
+    if accepted {
+        ic_cdk::api::call::accept_message();
+    }
+}
+
+#[init]
+fn init() {
+    bootstrap_owner();
+    seed_default_rules();
+    start_timers();
+}
+
 #[pre_upgrade]
 fn pre_upgrade() {
     // Save state before upgrade
 }
 
 #[post_upgrade]
-fn post_upgrade() {}
\ No newline at end of file
+fn post_upgrade() {
+    // Canisters installed before roles existed get the upgrading controller as owner
+    bootstrap_owner();
+    seed_default_rules();
+    index_existing_handles();
+    backfill_join_dates();
+    seed_warning_ids();
+    start_timers();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, match_mode: MatchMode) -> ModerationRule {
+        ModerationRule {
+            id: 1,
+            pattern: pattern.to_string(),
+            match_mode,
+            severity: ModerationSeverity::Medium,
+            action: RuleAction::Hide,
+            enabled: true,
+            updated_by: Principal::anonymous(),
+            updated_at: 0,
+        }
+    }
+
+    fn matches(rule: &ModerationRule, content: &str) -> bool {
+        rule_matches(rule, content, &normalize_for_moderation(content))
+    }
+
+    #[test]
+    fn normalization_folds_case_accents_and_confusables() {
+        assert_eq!(normalize_for_moderation("HÂTE Straße").tokens, vec!["hate", "strasse"]);
+        // Cyrillic "а" and "е" look like their Latin counterparts
+        assert_eq!(normalize_for_moderation("hаtе").tokens, vec!["hate"]);
+        assert_eq!(normalize_for_moderation("ｈａｔｅ").tokens, vec!["hate"]);
+    }
+
+    #[test]
+    fn normalization_undoes_leetspeak_only_in_words() {
+        assert_eq!(normalize_for_moderation("h4te").tokens, vec!["hate"]);
+        assert_eq!(normalize_for_moderation("$pam").tokens, vec!["spam"]);
+        assert_eq!(normalize_for_moderation("call 555 0100").tokens, vec!["call", "555", "0100"]);
+    }
+
+    #[test]
+    fn normalization_collapses_spaced_out_letters() {
+        let normalized = normalize_for_moderation("i h a t e you");
+        assert_eq!(normalized.tokens, vec!["i", "h", "a", "t", "e", "you"]);
+        assert_eq!(normalized.letter_runs, vec!["ihate"]);
+        // Shorter runs are left alone
+        let normalized = normalize_for_moderation("a b test");
+        assert_eq!(normalized.collapsed, vec!["a", "b", "test"]);
+        assert!(normalized.letter_runs.is_empty());
+    }
+
+    #[test]
+    fn exact_word_rules_respect_word_boundaries() {
+        let hate = rule("hate", MatchMode::ExactWord);
+        assert!(matches(&hate, "I hate this"));
+        assert!(!matches(&hate, "whatever, see you at the chateau"));
+        assert!(!matches(&rule("fake", MatchMode::ExactWord), "Shakespeare's fakery"));
+    }
+
+    #[test]
+    fn exact_word_rules_catch_obfuscation() {
+        let hate = rule("hate", MatchMode::ExactWord);
+        assert!(matches(&hate, "h a t e"));
+        // Real one-letter words in front of the spaced-out ones
+        assert!(matches(&hate, "I h a t e you"));
+        assert!(matches(&rule("scam", MatchMode::ExactWord), "this is a s c a m"));
+        assert!(matches(&hate, "h4te"));
+        assert!(matches(&hate, "#HATE!"));
+        assert!(matches(&hate, "hâté"));
+    }
+
+    #[test]
+    fn exact_word_rules_match_phrases() {
+        let phrase = rule("buy now", MatchMode::ExactWord);
+        assert!(matches(&phrase, "Buy   NOW!!"));
+        assert!(!matches(&phrase, "buy it now"));
+    }
+
+    #[test]
+    fn substring_and_regex_rules_see_normalized_text() {
+        assert!(matches(&rule("hat", MatchMode::Substring), "whatever"));
+        assert!(matches(&rule("^free money$", MatchMode::Regex), "FR33 m0ney"));
+        assert!(!matches(&rule("(", MatchMode::Regex), "anything"));
+    }
+
+    const SECOND_NS: u64 = 1_000_000_000;
+
+    #[test]
+    fn rate_limit_admits_up_to_the_limit() {
+        let mut state = RateLimitState::default();
+        let window = 60 * SECOND_NS;
+        for i in 0..3 {
+            assert_eq!(state.admit(WriteAction::PostUpdate, 3, window, i * SECOND_NS), Ok(()));
+        }
+        // The first call leaves the window 60s after it was made, at t=60s
+        assert_eq!(state.admit(WriteAction::PostUpdate, 3, window, 10 * SECOND_NS), Err(50 * SECOND_NS));
+        // A rejected call doesn't count
+        assert_eq!(state.recent[0].1.len(), 3);
+    }
+
+    #[test]
+    fn rate_limit_window_slides() {
+        let mut state = RateLimitState::default();
+        let window = 60 * SECOND_NS;
+        for at in [0, 20, 40] {
+            state.admit(WriteAction::LikeUpdate, 3, window, at * SECOND_NS).unwrap();
+        }
+        assert!(state.admit(WriteAction::LikeUpdate, 3, window, 59 * SECOND_NS).is_err());
+        // At exactly 60s the first call has left the window
+        assert_eq!(state.admit(WriteAction::LikeUpdate, 3, window, 60 * SECOND_NS), Ok(()));
+        assert_eq!(state.admit(WriteAction::LikeUpdate, 3, window, 61 * SECOND_NS), Err(19 * SECOND_NS));
+        assert_eq!(state.recent[0].1, vec![20 * SECOND_NS, 40 * SECOND_NS, 60 * SECOND_NS]);
+    }
+
+    #[test]
+    fn rate_limit_waits_for_enough_calls_when_the_limit_shrinks() {
+        let mut state = RateLimitState::default();
+        let window = 60 * SECOND_NS;
+        for at in [0, 10, 20, 30] {
+            state.admit(WriteAction::Follow, 10, window, at * SECOND_NS).unwrap();
+        }
+        // A restricted limit of 2 leaves room for one more call only once the
+        // 20s call has left the window at 80s
+        assert_eq!(state.admit(WriteAction::Follow, 2, window, 35 * SECOND_NS), Err(45 * SECOND_NS));
+    }
+
+    #[test]
+    fn rate_limits_are_tracked_per_action() {
+        let mut state = RateLimitState::default();
+        let window = 60 * SECOND_NS;
+        state.admit(WriteAction::PostUpdate, 1, window, 0).unwrap();
+        assert!(state.admit(WriteAction::PostUpdate, 1, window, SECOND_NS).is_err());
+        assert_eq!(state.admit(WriteAction::FlagUpdate, 1, window, SECOND_NS), Ok(()));
+    }
+
+    #[test]
+    fn profile_text_skips_the_post_heuristics() {
+        assert!(filter_content("NEW YORK CITY FOREVER").is_some());
+        assert_eq!(profile_text(Some("NEW YORK CITY FOREVER".to_string()), "Location", 30).unwrap().as_deref(), Some("NEW YORK CITY FOREVER"));
+        assert!(profile_text(Some("Sooooo happy".to_string()), "Bio", 160).unwrap().is_some());
+    }
+
+    #[test]
+    fn handles_accept_the_frontend_default() {
+        let principal = Principal::from_slice(&[7; 29]).to_text();
+        let default_handle = format!("user_{}", principal.replace('-', "").chars().take(10).collect::<String>());
+        assert!(validate_handle(&default_handle).is_ok());
+        assert!(validate_handle("Alice_99").is_ok());
+    }
+
+    #[test]
+    fn handles_are_checked_for_length_charset_and_reserved_names() {
+        assert!(validate_handle("ab").is_err());
+        assert!(validate_handle("abc").is_ok());
+        assert!(validate_handle(&"a".repeat(MAX_HANDLE_LENGTH)).is_ok());
+        assert!(validate_handle(&"a".repeat(MAX_HANDLE_LENGTH + 1)).is_err());
+        assert!(validate_handle("user_rrkah-fq").is_err());
+        assert!(validate_handle("héllo").is_err());
+        assert!(validate_handle("Admin").is_err());
+    }
+
+    fn near_duplicates(a: &str, b: &str) -> bool {
+        let alice = Principal::from_slice(&[1]);
+        is_near_duplicate(&fingerprint(alice, a, 0), &fingerprint(alice, b, 0), &SpamPolicy::default())
+    }
+
+    #[test]
+    fn simhash_is_stable_and_close_for_similar_texts() {
+        let tokens = |text: &str| normalize_for_moderation(text).tokens;
+        let original = simhash(&tokens("Check out my new album, streaming everywhere tonight"));
+        assert_eq!(original, simhash(&tokens("check out my NEW album streaming everywhere tonight!")));
+        let tweaked = simhash(&tokens("Check out my new album, streaming everywhere tomorrow"));
+        let unrelated = simhash(&tokens("The council meeting moved to Thursday because of the storm"));
+        assert!((original ^ tweaked).count_ones() < (original ^ unrelated).count_ones());
+        assert_eq!(simhash(&[]), 0);
+    }
+
+    #[test]
+    fn near_duplicates_ignore_case_punctuation_and_small_edits() {
+        let post = "Huge giveaway today, follow and repost to win a brand new phone";
+        assert!(near_duplicates(post, "HUGE giveaway today!!! follow & repost to win a brand new phone"));
+        assert!(near_duplicates(post, "Huge giveaway today, follow and repost to win a brand new laptop"));
+        assert!(!near_duplicates(post, "Our team shipped the new release today, thanks to every contributor"));
+    }
+
+    #[test]
+    fn short_texts_only_match_exactly() {
+        assert!(near_duplicates("good morning", "Good morning!"));
+        assert!(!near_duplicates("good morning", "good evening"));
+    }
+
+    #[test]
+    fn texts_without_tokens_never_match() {
+        assert!(!near_duplicates("🎉🎉🎉", "🎉🎉🎉"));
+        assert!(!near_duplicates("!!!", "???"));
+    }
+
+    fn export_document(chunk: &ExportChunk) -> serde_json::Value {
+        serde_json::from_str(&chunk.data).unwrap()
+    }
+
+    // Each item serializes to exactly ten bytes: {"n":"00"}
+    fn numbered_items(count: usize) -> Vec<serde_json::Value> {
+        (0..count).map(|n| json!({ "n": format!("{:02}", n) })).collect()
+    }
+
+    #[test]
+    fn export_chunks_split_on_the_byte_budget() {
+        let items = numbered_items(5);
+        let cursor = ExportCursor { section: ExportSection::Likes, offset: 0 };
+        let chunk = export_chunk(&cursor, &items, 25).unwrap();
+        let document = export_document(&chunk);
+        assert_eq!(document["format_version"], EXPORT_FORMAT_VERSION);
+        assert_eq!(document["section"], "Likes");
+        assert_eq!(document["offset"], 0);
+        assert_eq!(document["total"], 5);
+        assert_eq!(document["items"].as_array().unwrap().len(), 2);
+        
+        let next = chunk.next_cursor.unwrap();
+        assert_eq!((next.section, next.offset), (ExportSection::Likes, 2));
+        let document = export_document(&export_chunk(&next, &items, 25).unwrap());
+        assert_eq!(document["offset"], 2);
+        assert_eq!(document["items"][0]["n"], "02");
+    }
+
+    #[test]
+    fn export_chunk_filling_the_budget_exactly_keeps_every_item() {
+        let items = numbered_items(3);
+        let cursor = ExportCursor { section: ExportSection::Likes, offset: 0 };
+        let chunk = export_chunk(&cursor, &items, 30).unwrap();
+        assert_eq!(export_document(&chunk)["items"].as_array().unwrap().len(), 3);
+        let next = chunk.next_cursor.unwrap();
+        assert_eq!((next.section, next.offset), (ExportSection::Following, 0));
+    }
+
+    #[test]
+    fn export_chunk_always_takes_an_oversized_item() {
+        let items = numbered_items(2);
+        let cursor = ExportCursor { section: ExportSection::Updates, offset: 0 };
+        let chunk = export_chunk(&cursor, &items, 5).unwrap();
+        assert_eq!(export_document(&chunk)["items"].as_array().unwrap().len(), 1);
+        assert_eq!(chunk.next_cursor.unwrap().offset, 1);
+    }
+
+    #[test]
+    fn export_moves_through_empty_sections_and_ends_after_reports() {
+        let empty = ExportCursor { section: ExportSection::Warnings, offset: 0 };
+        let chunk = export_chunk(&empty, &[], 100).unwrap();
+        assert_eq!(export_document(&chunk)["total"], 0);
+        assert_eq!(chunk.next_cursor.unwrap().section, ExportSection::Reports);
+        
+        let last = ExportCursor { section: ExportSection::Reports, offset: 0 };
+        assert!(export_chunk(&last, &numbered_items(1), 100).unwrap().next_cursor.is_none());
+    }
+
+    #[test]
+    fn export_rejects_cursors_past_the_end() {
+        let cursor = ExportCursor { section: ExportSection::Likes, offset: 3 };
+        assert!(export_chunk(&cursor, &numbered_items(2), 100).is_err());
+        // A cursor right at the end is an empty but valid chunk
+        let cursor = ExportCursor { section: ExportSection::Likes, offset: 2 };
+        assert!(export_chunk(&cursor, &numbered_items(2), 100).is_ok());
+    }
+}
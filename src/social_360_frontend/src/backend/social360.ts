@@ -62,10 +62,43 @@ function stringToPrincipal(principalString: string): Principal {
   }
 }
 
+type CandidResult<T> = { Ok: T } | { Err: unknown };
+
+// Unwraps a canister Result, throwing on the Err variant
+function unwrapResult<T>(result: unknown): T {
+  const typed = result as CandidResult<T>;
+  if ('Err' in typed) {
+    const detail = JSON.stringify(typed.Err, (_, value) =>
+      typeof value === 'bigint' ? value.toString() : value
+    );
+    throw new Error(`Backend error: ${detail}`);
+  }
+  return typed.Ok;
+}
+
+type FeedItem = {
+  update: Record<string, unknown>;
+  blurred_labels: unknown[];
+  notice: [] | [string];
+  interstitial: [] | [string];
+  original: [] | [Record<string, unknown>];
+};
+
+// Flattens feed items so callers keep reading update fields directly
+function flattenFeed(items: unknown) {
+  return (items as FeedItem[]).map((item) => ({
+    ...item.update,
+    blurred_labels: item.blurred_labels,
+    notice: item.notice[0],
+    interstitial: item.interstitial[0],
+    original: item.original[0],
+  }));
+}
+
 // Backend API wrappers
 export async function register(handle: string) {
   const actor = await getActor();
-  return actor.register(handle);
+  return unwrapResult<null>(await actor.register(handle));
 }
 
 export async function getUser(principal: string) {
@@ -74,30 +107,127 @@ export async function getUser(principal: string) {
   return actor.get_user(principalObj);
 }
 
-export async function postUpdate(content: string) {
+export interface ProfileFields {
+  name: string;
+  bio: string;
+  location: string;
+  website: string;
+  avatarUrl?: string;
+  bannerUrl?: string;
+}
+
+// Candid opt: empty strings clear the field
+const optText = (value?: string) => (value ? [value] : []);
+
+export async function updateProfile(profile: ProfileFields) {
+  const actor = await getActor();
+  return unwrapResult(
+    await actor.update_profile({
+      display_name: optText(profile.name),
+      bio: optText(profile.bio),
+      location: optText(profile.location),
+      website: optText(profile.website),
+      avatar_url: optText(profile.avatarUrl),
+      banner_url: optText(profile.bannerUrl),
+    })
+  );
+}
+
+export async function changeHandle(newHandle: string) {
+  const actor = await getActor();
+  return unwrapResult<null>(await actor.change_handle(newHandle));
+}
+
+export async function requestAccountDeletion() {
+  const actor = await getActor();
+  return unwrapResult(await actor.request_account_deletion());
+}
+
+export async function cancelAccountDeletion() {
+  const actor = await getActor();
+  return unwrapResult<null>(await actor.cancel_account_deletion());
+}
+
+type ExportCursor = { section: Record<string, null>; offset: bigint };
+
+type ExportChunk = {
+  format_version: number;
+  section: Record<string, null>;
+  data: string;
+  next_cursor: [] | [ExportCursor];
+};
+
+// Follows the export cursor to the end and merges every chunk's items by section
+export async function exportMyData() {
+  const actor = await getActor();
+  const sections: Record<string, unknown[]> = {};
+  let formatVersion = 0;
+  let cursor: [] | [ExportCursor] = [];
+  do {
+    const chunk = unwrapResult<ExportChunk>(await actor.export_my_data(cursor));
+    const document = JSON.parse(chunk.data) as { section: string; items: unknown[] };
+    formatVersion = chunk.format_version;
+    sections[document.section] = [...(sections[document.section] ?? []), ...document.items];
+    cursor = chunk.next_cursor;
+  } while (cursor.length > 0);
+  return { format_version: formatVersion, exported_at: new Date().toISOString(), sections };
+}
+
+export async function getUserByHandle(handle: string) {
   const actor = await getActor();
-  return actor.post_update(content);
+  return actor.get_user_by_handle(handle);
+}
+
+export type ContentLabel = 'Nsfw' | 'Spoiler' | 'Graphic' | 'Sensitive';
+
+export async function postUpdate(content: string, labels: ContentLabel[] = []) {
+  const actor = await getActor();
+  return unwrapResult<bigint>(await actor.post_update(content, [labels.map((label) => ({ [label]: null }))]));
 }
 
 export async function repostUpdate(postId: bigint) {
   const actor = await getActor();
-  return actor.repost_update(postId);
+  return unwrapResult<bigint>(await actor.repost_update(postId));
 }
 
 export async function quoteUpdate(postId: bigint, quoteContent: string) {
   const actor = await getActor();
-  return actor.quote_update(postId, quoteContent);
+  return unwrapResult<bigint>(await actor.quote_update(postId, quoteContent));
 }
 
+export type FollowState = 'Following' | 'Requested';
+
+// Private accounts turn a follow into a pending request
 export async function follow(principal: string) {
   const actor = await getActor();
   const principalObj = stringToPrincipal(principal);
-  return actor.follow(principalObj);
+  const state = unwrapResult<Record<FollowState, null>>(await actor.follow(principalObj));
+  return Object.keys(state)[0] as FollowState;
+}
+
+export async function setAccountPrivacy(isPrivate: boolean) {
+  const actor = await getActor();
+  return unwrapResult<null>(await actor.set_account_privacy(isPrivate));
+}
+
+export async function getFollowRequests() {
+  const actor = await getActor();
+  return actor.get_follow_requests();
+}
+
+export async function approveFollowRequest(principal: string) {
+  const actor = await getActor();
+  return unwrapResult<null>(await actor.approve_follow_request(stringToPrincipal(principal)));
+}
+
+export async function denyFollowRequest(principal: string) {
+  const actor = await getActor();
+  return unwrapResult<null>(await actor.deny_follow_request(stringToPrincipal(principal)));
 }
 
 export async function likeUpdate(postId: bigint) {
   const actor = await getActor();
-  return actor.like_update(postId);
+  return unwrapResult<boolean>(await actor.like_update(postId));
 }
 
 export async function hasLikedUpdate(postId: bigint, principal: string) {
@@ -114,12 +244,12 @@ export async function hasRepostedUpdate(postId: bigint, principal: string) {
 
 export async function getTimeline(page: number, pageSize: number) {
   const actor = await getActor();
-  return actor.get_timeline(BigInt(page), BigInt(pageSize));
+  return flattenFeed(await actor.get_timeline(BigInt(page), BigInt(pageSize)));
 }
 
 export async function searchUpdates(keyword: string) {
   const actor = await getActor();
-  return actor.search_updates(keyword);
+  return flattenFeed(await actor.search_updates(keyword));
 }
 
 export async function searchUsers(handlePrefix: string) {
@@ -130,7 +260,7 @@ export async function searchUsers(handlePrefix: string) {
 export async function getUserUpdates(principal: string, page: number, pageSize: number) {
   const actor = await getActor();
   const principalObj = stringToPrincipal(principal);
-  return actor.get_user_updates(principalObj, BigInt(page), BigInt(pageSize));
+  return flattenFeed(await actor.get_user_updates(principalObj, BigInt(page), BigInt(pageSize)));
 }
 
 export async function getFollowers(principal: string) {
@@ -148,12 +278,19 @@ export async function getFollowing(principal: string) {
 // New moderation functions
 export async function flagUpdate(postId: bigint, reason: string, severity: string) {
   const actor = await getActor();
-  return actor.flag_update(postId, reason, severity);
+  return unwrapResult<bigint>(await actor.flag_update(postId, reason, severity));
+}
+
+export type FlagOutcome = 'Dismissed' | 'ContentRemoved' | 'AuthorWarned' | 'Escalated';
+
+export async function resolveFlag(flagId: bigint, outcome: FlagOutcome, note?: string) {
+  const actor = await getActor();
+  return unwrapResult<null>(await actor.resolve_flag(flagId, { [outcome]: null }, note ? [note] : []));
 }
 
-export async function resolveFlag(flagId: bigint) {
+export async function getMyReports() {
   const actor = await getActor();
-  return actor.resolve_flag(flagId);
+  return actor.get_my_reports();
 }
 
 export async function moderateUpdate(postId: bigint, reason: string, severity: string) {
@@ -149,10 +149,11 @@ const Explore: React.FC = () => {
     if (!user) return;
 
     try {
-      await backend.follow(userId);
+      const state = await backend.follow(userId);
+      // A private account only shows up as followed once it approves
       setSuggestedUsers((users) =>
         users.map((user) =>
-          user.id === userId
+          user.id === userId && state === "Following"
             ? { ...user, isFollowing: !user.isFollowing }
             : user
         )
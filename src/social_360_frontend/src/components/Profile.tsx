@@ -59,25 +59,32 @@ const Profile: React.FC = () => {
     if (!user) return;
 
     try {
-      // For now, create a mock profile since backend doesn't have full profile endpoints
-      const mockProfile: UserProfile = {
-        handle: user.handle,
-        name: `User ${user.handle}`,
-        bio: "This is my bio. I love Social360!",
-        location: "Internet Computer",
-        website: "https://social360.ic",
-        joinDate: new Date("2024-01-01"),
-        followers: 42,
-        following: 23,
-        posts: 15,
+      const [[record], followers, following] = await Promise.all([
+        backend.getUser(user.principal) as Promise<any[]>,
+        backend.getFollowers(user.principal) as Promise<unknown[]>,
+        backend.getFollowing(user.principal) as Promise<unknown[]>,
+      ]);
+
+      const loadedProfile: UserProfile = {
+        handle: record?.handle ?? user.handle,
+        name: record?.display_name?.[0] ?? user.handle,
+        bio: record?.bio?.[0] ?? "",
+        location: record?.location?.[0] ?? "",
+        website: record?.website?.[0] ?? "",
+        joinDate: record?.joined_at?.[0]
+          ? new Date(Number(record.joined_at[0]) / 1000000)
+          : new Date(),
+        followers: followers.length,
+        following: following.length,
+        posts: posts.length,
       };
 
-      setProfile(mockProfile);
+      setProfile(loadedProfile);
       setEditForm({
-        name: mockProfile.name,
-        bio: mockProfile.bio,
-        location: mockProfile.location,
-        website: mockProfile.website,
+        name: loadedProfile.name,
+        bio: loadedProfile.bio,
+        location: loadedProfile.location,
+        website: loadedProfile.website,
       });
     } catch (error) {
       console.error("Failed to fetch profile:", error);
@@ -116,6 +123,9 @@ const Profile: React.FC = () => {
       );
 
       setPosts(formattedPosts);
+      setProfile((prev) =>
+        prev ? { ...prev, posts: formattedPosts.length } : null
+      );
     } catch (error) {
       console.error("Failed to fetch user posts:", error);
     } finally {
@@ -125,7 +135,7 @@ const Profile: React.FC = () => {
 
   const handleSaveProfile = async () => {
     try {
-      // In a real implementation, you'd call backend.updateProfile(editForm)
+      await backend.updateProfile(editForm);
       setProfile((prev) => (prev ? { ...prev, ...editForm } : null));
       setIsEditing(false);
     } catch (error) {
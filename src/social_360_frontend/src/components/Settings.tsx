@@ -1,4 +1,4 @@
-import React, { useState } from "react";
+import React, { useEffect, useState } from "react";
 import { motion } from "framer-motion";
 import {
   User,
@@ -17,6 +17,7 @@ import {
 } from "lucide-react";
 import { useAuth } from "../contexts/AuthContext";
 import { useTheme } from "../contexts/ThemeContext";
+import * as backend from "../backend/social360";
 
 interface SettingsSection {
   id: string;
@@ -41,6 +42,38 @@ const Settings: React.FC = () => {
   const [emailNotifications, setEmailNotifications] = useState(false);
   const [privacyMode, setPrivacyMode] = useState(false);
 
+  useEffect(() => {
+    if (!user) return;
+    backend
+      .getUser(user.principal)
+      .then((profile: any) => setPrivacyMode(profile?.[0]?.is_private?.[0] ?? false))
+      .catch((error) => console.error("Failed to load privacy setting:", error));
+  }, [user]);
+
+  const togglePrivacy = async () => {
+    try {
+      await backend.setAccountPrivacy(!privacyMode);
+      setPrivacyMode(!privacyMode);
+    } catch (error) {
+      console.error("Failed to update privacy setting:", error);
+    }
+  };
+
+  const downloadData = async () => {
+    try {
+      const data = await backend.exportMyData();
+      const blob = new Blob([JSON.stringify(data, null, 2)], { type: "application/json" });
+      const url = URL.createObjectURL(blob);
+      const link = document.createElement("a");
+      link.href = url;
+      link.download = `connect-data-${user?.handle || "export"}.json`;
+      link.click();
+      URL.revokeObjectURL(url);
+    } catch (error) {
+      console.error("Failed to export data:", error);
+    }
+  };
+
   const settingsSections: SettingsSection[] = [
     {
       id: "account",
@@ -80,7 +113,7 @@ const Settings: React.FC = () => {
           subtitle: "Only approved followers can see your posts",
           type: "toggle",
           value: privacyMode,
-          onClick: () => setPrivacyMode(!privacyMode),
+          onClick: togglePrivacy,
         },
         {
           id: "blocked",
@@ -191,7 +224,7 @@ const Settings: React.FC = () => {
           title: "Download Your Data",
           subtitle: "Get a copy of your data",
           type: "button",
-          onClick: () => console.log("Download data"),
+          onClick: downloadData,
         },
       ],
     },
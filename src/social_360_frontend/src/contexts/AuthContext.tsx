@@ -86,8 +86,8 @@ export const AuthProvider: React.FC<{ children: React.ReactNode }> = ({
       ) {
         handle = (profile as any).handle;
       } else {
-        // If not registered, register with a default handle
-        handle = `user_${principal.slice(0, 8)}`;
+        // If not registered, register with a default handle; handles can't contain the dashes in principal text
+        handle = `user_${principal.replace(/-/g, "").slice(0, 10)}`;
         try {
           await backend.register(handle);
           // Fetch again to ensure registration
@@ -169,8 +169,8 @@ export const AuthProvider: React.FC<{ children: React.ReactNode }> = ({
           ) {
             handle = (profile as any).handle;
           } else {
-            // If not registered, register with a default handle
-            handle = `user_${principal.slice(0, 8)}`;
+            // If not registered, register with a default handle; handles can't contain the dashes in principal text
+            handle = `user_${principal.replace(/-/g, "").slice(0, 10)}`;
             try {
               await backend.register(handle);
               // Fetch again to ensure registration